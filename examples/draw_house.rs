@@ -4,12 +4,18 @@ use enigo::Enigo;
 use passpartout_printer::colors::PaletteColor;
 use passpartout_printer::coords::Coord;
 use passpartout_printer::easel::Easel;
+use passpartout_printer::profile;
+use std::env;
 use std::error::Error;
 use std::time::Duration;
 
 fn app() -> Result<(), Box<dyn Error>> {
     let enigo = Enigo::new();
-    let mut easel = Easel::new("coords.json".to_string(), enigo, Duration::from_millis(10))?;
+    // Pass a profile name to draw with that profile instead of coords.json.
+    let profile = env::args().nth(1);
+    let config_path = profile::config_path(profile.as_deref(), None);
+    let config_path = config_path.to_string_lossy().into_owned();
+    let mut easel = Easel::new(config_path, enigo, Duration::from_millis(10))?;
 
     // First, draw the background sky.
     easel.change_brush_size(16);
@@ -0,0 +1,457 @@
+use crate::colors::{Palette, PaletteColor};
+use crate::coords::Coord;
+use crate::easel::{EaselCoords, BRUSH_WIDTHS};
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use std::error::Error;
+use std::path::Path;
+
+/// How far each channel of a pixel may be from a palette color and still count as that color.
+const COLOR_TOLERANCE: i32 = 12;
+
+/// The distance between neighbouring palette swatches in the screenshot the button templates
+/// were cut from. Templates are scaled by how far this is from the distance in the screenshot
+/// being configured from.
+const TEMPLATE_COLOR_STEP: f32 = 29.0;
+
+/// How far a button template is allowed to be from the screenshot, as the root mean square
+/// difference of the color channels.
+const MAX_TEMPLATE_ERROR: f32 = 40.0;
+
+/// A pixel that doesn't match any palette color.
+const NO_COLOR: u8 = u8::MAX;
+
+/// A connected area of the screenshot that matches a single palette color.
+struct Region {
+    color: u8,
+    area: usize,
+    min: Coord,
+    max: Coord,
+    sum_x: i64,
+    sum_y: i64,
+}
+
+impl Region {
+    fn width(&self) -> i32 {
+        self.max.x - self.min.x + 1
+    }
+
+    fn height(&self) -> i32 {
+        self.max.y - self.min.y + 1
+    }
+
+    fn center(&self) -> (f32, f32) {
+        (
+            self.sum_x as f32 / self.area as f32,
+            self.sum_y as f32 / self.area as f32,
+        )
+    }
+
+    /// Whether the region could be one of the roughly round blobs of paint on the palette.
+    fn is_swatch(&self) -> bool {
+        let (width, height) = (self.width(), self.height());
+        width >= 4
+            && height >= 4
+            && width <= 100
+            && height <= 100
+            && width.max(height) <= width.min(height) * 2
+            && self.area * 2 >= (width * height) as usize
+    }
+}
+
+/// Works out the configuration of the easel from a screenshot of the game, instead of clicking
+/// on every element by hand. The easel has to be open, blank and fully visible.
+///
+/// The palette is found by looking for the colors of `PaletteColor` laid out in a grid, and the
+/// easel is the largest area of blank canvas. The buttons are found by matching them against
+/// the images in `templates`, which are named after the fields of `EaselCoords` they fill in.
+///
+/// Only the orientation the easel is in can be seen, so the other orientation is assumed to be
+/// the same size turned on its side around the same center.
+pub fn auto_configure(
+    screenshot: &RgbaImage,
+    templates: &str,
+) -> Result<EaselCoords, Box<dyn Error>> {
+    let palette = Palette::new();
+    let regions = find_regions(screenshot, &palette);
+
+    let (color_start, color_row_step, color_col_step) = find_palette(&regions, &palette)?;
+    println!(
+        "Found the palette at {:?} with steps of {} and {}.",
+        color_start, color_row_step, color_col_step
+    );
+
+    let (ul, lr) = find_easel(&regions, &palette, screenshot)?;
+    let turned = turn_bounds(ul, lr);
+    let (portrait_bounds, landscape_bounds) = if lr.x - ul.x > lr.y - ul.y {
+        println!("Found the easel in landscape at {:?} to {:?}.", ul, lr);
+        (turned, (ul, lr))
+    } else {
+        println!("Found the easel in portrait at {:?} to {:?}.", ul, lr);
+        ((ul, lr), turned)
+    };
+
+    // The buttons all sit on the same panel as the palette, so only search around it.
+    let scale = color_row_step as f32 / TEMPLATE_COLOR_STEP;
+    let search = (
+        Coord::new(
+            color_start.x - 7 * color_row_step,
+            color_start.y - 3 * color_col_step,
+        ),
+        Coord::new(
+            color_start.x + 10 * color_row_step,
+            color_start.y + 6 * color_col_step,
+        ),
+    );
+    let find = |name: &str| -> Result<Coord, Box<dyn Error>> {
+        let path = Path::new(templates).join(format!("{}.png", name));
+        let template = image::open(&path)?.into_rgba8();
+        let found = find_template(screenshot, &template, scale, search)
+            .ok_or_else(|| format!("couldn't find the {} button", name))?;
+        println!("Found the {} button at {:?}.", name, found);
+        Ok(found)
+    };
+
+    Ok(EaselCoords {
+        portrait_bounds,
+        landscape_bounds,
+        paintbrush: find("paintbrush")?,
+        spray_can: find("spray_can")?,
+        pen: find("pen")?,
+        decrease_brush: find("decrease_brush")?,
+        increase_brush: find("increase_brush")?,
+        change_orientation: find("change_orientation")?,
+        color_start,
+        color_row_step,
+        color_col_step,
+        brush_widths: BRUSH_WIDTHS.to_vec(),
+        screen_size: Some(screenshot.dimensions()),
+        window: None,
+    })
+}
+
+/// Matches every pixel of the screenshot to a palette color, if it's close enough to one, and
+/// gathers the matching pixels into connected regions.
+fn find_regions(screenshot: &RgbaImage, palette: &Palette) -> Vec<Region> {
+    let (width, height) = screenshot.dimensions();
+    let (width, height) = (width as usize, height as usize);
+    let colors: Vec<[i32; 3]> = palette
+        .colormap
+        .iter()
+        .map(|color| {
+            let rgba = color.get_rgba();
+            [rgba[0].into(), rgba[1].into(), rgba[2].into()]
+        })
+        .collect();
+
+    let mut labels: Vec<u8> = screenshot
+        .pixels()
+        .map(|pixel| {
+            colors
+                .iter()
+                .position(|color| {
+                    (0..3).all(|c| (i32::from(pixel[c]) - color[c]).abs() <= COLOR_TOLERANCE)
+                })
+                .map_or(NO_COLOR, |index| index as u8)
+        })
+        .collect();
+
+    // Flood fill each region, clearing its pixels as they're visited.
+    let mut regions = vec![];
+    let mut stack = vec![];
+    for start in 0..labels.len() {
+        let color = labels[start];
+        if color == NO_COLOR {
+            continue;
+        }
+        let mut region = Region {
+            color,
+            area: 0,
+            min: Coord::new(i32::MAX, i32::MAX),
+            max: Coord::new(i32::MIN, i32::MIN),
+            sum_x: 0,
+            sum_y: 0,
+        };
+        labels[start] = NO_COLOR;
+        stack.push(start);
+        while let Some(index) = stack.pop() {
+            let (x, y) = (index % width, index / width);
+            region.area += 1;
+            region.sum_x += x as i64;
+            region.sum_y += y as i64;
+            region.min = Coord::new(region.min.x.min(x as i32), region.min.y.min(y as i32));
+            region.max = Coord::new(region.max.x.max(x as i32), region.max.y.max(y as i32));
+
+            let neighbours = [
+                (x > 0).then(|| index - 1),
+                (x + 1 < width).then(|| index + 1),
+                (y > 0).then(|| index - width),
+                (y + 1 < height).then(|| index + width),
+            ];
+            for neighbour in neighbours.into_iter().flatten() {
+                if labels[neighbour] == color {
+                    labels[neighbour] = NO_COLOR;
+                    stack.push(neighbour);
+                }
+            }
+        }
+        regions.push(region);
+    }
+    regions
+}
+
+/// Finds the grid of palette swatches, returning the position of the first swatch and the
+/// distances between rows and columns the same way `EaselCoords` stores them.
+fn find_palette(
+    regions: &[Region],
+    palette: &Palette,
+) -> Result<(Coord, i32, i32), Box<dyn Error>> {
+    let mut swatches: Vec<Vec<(f32, f32)>> = vec![vec![]; palette.colormap.len()];
+    for region in regions.iter().filter(|region| region.is_swatch()) {
+        swatches[region.color as usize].push(region.center());
+    }
+    let swatches_of = |color: PaletteColor| {
+        let index = palette.colormap.iter().position(|c| *c == color).unwrap();
+        &swatches[index]
+    };
+
+    // Guess at the grid from every combination of the swatches next to the first one, keeping
+    // whichever guess lines up with the most colors.
+    let mut best: Option<Vec<(Coord, (f32, f32))>> = None;
+    for &start in swatches_of(PaletteColor::Black) {
+        for &next_row in swatches_of(PaletteColor::DarkBrown) {
+            let row_step = (next_row.0 - start.0, next_row.1 - start.1);
+            if row_step.0 <= 0.0 || row_step.1.abs() * 4.0 > row_step.0 {
+                continue;
+            }
+            for &next_col in swatches_of(PaletteColor::Grey) {
+                let col_step = (next_col.0 - start.0, next_col.1 - start.1);
+                if col_step.1 <= 0.0 || col_step.0.abs() * 4.0 > col_step.1 {
+                    continue;
+                }
+
+                let reach = row_step.0.max(col_step.1) / 3.0;
+                let matched: Vec<(Coord, (f32, f32))> = palette
+                    .colormap
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, color)| {
+                        let pos = color.get_row_col();
+                        let expected = (
+                            start.0 + row_step.0 * pos.x as f32 + col_step.0 * pos.y as f32,
+                            start.1 + row_step.1 * pos.x as f32 + col_step.1 * pos.y as f32,
+                        );
+                        swatches[index]
+                            .iter()
+                            .find(|swatch| {
+                                (swatch.0 - expected.0).abs() <= reach
+                                    && (swatch.1 - expected.1).abs() <= reach
+                            })
+                            .map(|swatch| (pos, *swatch))
+                    })
+                    .collect();
+                if best
+                    .as_ref()
+                    .map_or(true, |best| matched.len() > best.len())
+                {
+                    best = Some(matched);
+                }
+            }
+        }
+    }
+
+    let matched = match best {
+        Some(matched) if matched.len() * 6 >= palette.colormap.len() * 5 => matched,
+        Some(matched) => Err(format!(
+            "only found {} of the {} palette colors",
+            matched.len(),
+            palette.colormap.len()
+        ))?,
+        None => Err("couldn't find the palette")?,
+    };
+
+    // Fit the grid to every swatch found rather than just the first few.
+    let (start_x, row_step) = fit_line(matched.iter().map(|(pos, center)| (pos.x, center.0)));
+    let (start_y, col_step) = fit_line(matched.iter().map(|(pos, center)| (pos.y, center.1)));
+    Ok((
+        Coord::new(start_x.round() as i32, start_y.round() as i32),
+        row_step.round() as i32,
+        col_step.round() as i32,
+    ))
+}
+
+/// Fits `value = start + step * index` to the given points by least squares, returning the
+/// start and step.
+fn fit_line<I: Iterator<Item = (i32, f32)>>(points: I) -> (f32, f32) {
+    let points: Vec<(f32, f32)> = points.map(|(index, value)| (index as f32, value)).collect();
+    let count = points.len() as f32;
+    let mean_index = points.iter().map(|p| p.0).sum::<f32>() / count;
+    let mean_value = points.iter().map(|p| p.1).sum::<f32>() / count;
+    let covariance: f32 = points
+        .iter()
+        .map(|p| (p.0 - mean_index) * (p.1 - mean_value))
+        .sum();
+    let variance: f32 = points.iter().map(|p| (p.0 - mean_index).powi(2)).sum();
+    let step = if variance == 0.0 {
+        0.0
+    } else {
+        covariance / variance
+    };
+    (mean_value - step * mean_index, step)
+}
+
+/// Finds the easel as the largest area of blank canvas, returning its upper-left and
+/// lower-right corners pulled in slightly from the edges.
+fn find_easel(
+    regions: &[Region],
+    palette: &Palette,
+    screenshot: &RgbaImage,
+) -> Result<(Coord, Coord), Box<dyn Error>> {
+    let white = palette
+        .colormap
+        .iter()
+        .position(|color| *color == PaletteColor::White)
+        .unwrap() as u8;
+    let screen_area = (screenshot.width() * screenshot.height()) as usize;
+    let easel = regions
+        .iter()
+        .filter(|region| region.color == white)
+        .max_by_key(|region| region.area)
+        .filter(|region| {
+            region.area * 50 >= screen_area
+                && region.area * 10 >= (region.width() * region.height()) as usize * 9
+        })
+        .ok_or("couldn't find the easel, make sure it's blank and fully visible")?;
+
+    // Clicking right on the edge of the easel can miss it, so stay a little inside.
+    let inset = Coord::new(2, 2);
+    Ok((easel.min + &inset, easel.max - &inset))
+}
+
+/// Turns the bounds of the easel on their side around their center.
+fn turn_bounds(ul: Coord, lr: Coord) -> (Coord, Coord) {
+    let center = Coord::new((ul.x + lr.x) / 2, (ul.y + lr.y) / 2);
+    let half_width = (lr.x - ul.x) / 2;
+    let half_height = (lr.y - ul.y) / 2;
+    (
+        Coord::new(center.x - half_height, center.y - half_width),
+        Coord::new(center.x + half_height, center.y + half_width),
+    )
+}
+
+/// Finds where a template, scaled by `scale`, best matches the screenshot within the search
+/// area, returning its center. Gives up if even the best match is too far from the template.
+fn find_template(
+    screenshot: &RgbaImage,
+    template: &RgbaImage,
+    scale: f32,
+    (search_ul, search_lr): (Coord, Coord),
+) -> Option<Coord> {
+    let width = ((template.width() as f32 * scale).round() as u32).max(1);
+    let height = ((template.height() as f32 * scale).round() as u32).max(1);
+    let template = imageops::resize(template, width, height, FilterType::Triangle);
+
+    let max_x = (screenshot.width() as i32 - width as i32).min(search_lr.x);
+    let max_y = (screenshot.height() as i32 - height as i32).min(search_lr.y);
+    let min_x = search_ul.x.max(0);
+    let min_y = search_ul.y.max(0);
+    if min_x > max_x || min_y > max_y {
+        return None;
+    }
+
+    // Sum of squared differences, giving up early once it's worse than the best so far.
+    let difference = |x: i32, y: i32, limit: u64| -> u64 {
+        let mut total = 0;
+        for (tx, ty, want) in template.enumerate_pixels() {
+            let got = screenshot.get_pixel(x as u32 + tx, y as u32 + ty);
+            for c in 0..3 {
+                let diff = i64::from(want[c]) - i64::from(got[c]);
+                total += (diff * diff) as u64;
+            }
+            if total > limit {
+                break;
+            }
+        }
+        total
+    };
+
+    // Search coarsely first, then look closely around the best coarse match.
+    let mut best = (u64::MAX, Coord::new(min_x, min_y));
+    for y in (min_y..=max_y).step_by(2) {
+        for x in (min_x..=max_x).step_by(2) {
+            let total = difference(x, y, best.0);
+            if total < best.0 {
+                best = (total, Coord::new(x, y));
+            }
+        }
+    }
+    let coarse = best.1;
+    for y in (coarse.y - 1).max(min_y)..=(coarse.y + 1).min(max_y) {
+        for x in (coarse.x - 1).max(min_x)..=(coarse.x + 1).min(max_x) {
+            let total = difference(x, y, best.0);
+            if total < best.0 {
+                best = (total, Coord::new(x, y));
+            }
+        }
+    }
+
+    let error = (best.0 as f32 / (width * height * 3) as f32).sqrt();
+    if error > MAX_TEMPLATE_ERROR {
+        return None;
+    }
+    Some(best.1 + &Coord::new(width as i32 / 2, height as i32 / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// A screenshot with a blank portrait easel from (150, 100) to (450, 550), the palette
+    /// starting at (650, 150) with 29 pixels between swatches, and the button templates pasted
+    /// in a row above it.
+    fn screenshot() -> RgbaImage {
+        image::open("tests/fixtures/easel_screenshot.png")
+            .unwrap()
+            .into_rgba8()
+    }
+
+    fn assert_near(found: Coord, expected: (i32, i32)) {
+        assert!(
+            (found.x - expected.0).abs() <= 2 && (found.y - expected.1).abs() <= 2,
+            "found {:?}, expected about {:?}",
+            found,
+            expected
+        );
+    }
+
+    #[test]
+    fn configures_from_a_screenshot() {
+        let coords = auto_configure(&screenshot(), "templates").unwrap();
+
+        assert_near(coords.color_start, (650, 150));
+        assert_eq!(coords.color_row_step, 29);
+        assert_eq!(coords.color_col_step, 29);
+
+        assert_near(coords.portrait_bounds.0, (152, 102));
+        assert_near(coords.portrait_bounds.1, (448, 548));
+        assert_near(coords.landscape_bounds.0, (77, 177));
+        assert_near(coords.landscape_bounds.1, (523, 473));
+
+        assert_near(coords.paintbrush, (486, 86));
+        assert_near(coords.spray_can, (530, 86));
+        assert_near(coords.pen, (574, 86));
+        assert_near(coords.decrease_brush, (618, 86));
+        assert_near(coords.increase_brush, (662, 86));
+        assert_near(coords.change_orientation, (706, 86));
+
+        assert_eq!(coords.screen_size, Some((1000, 700)));
+        assert_eq!(coords.check(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn a_blank_screenshot_is_rejected() {
+        let blank = RgbaImage::from_pixel(1000, 700, Rgba([90, 70, 110, 255]));
+        assert!(auto_configure(&blank, "templates").is_err());
+    }
+}
@@ -0,0 +1,38 @@
+use enigo::{Enigo, MouseButton, MouseControllable};
+use std::thread;
+use std::time::Duration;
+
+/// The operations an easel needs from whatever is injecting input into the game. Every mouse
+/// movement, click and wait the easel performs goes through this trait, so drawing can be
+/// redirected away from the real mouse (for example, to run headless).
+pub trait PointerBackend {
+    /// Moves the cursor to the given screen coordinates.
+    fn move_to(&mut self, x: i32, y: i32);
+
+    /// Presses and holds the left mouse button.
+    fn press(&mut self);
+
+    /// Releases the left mouse button.
+    fn release(&mut self);
+
+    /// Waits for the given amount of time. Backends that don't talk to the game are free to
+    /// return immediately.
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// Drives the real mouse through Enigo.
+impl PointerBackend for Enigo {
+    fn move_to(&mut self, x: i32, y: i32) {
+        self.mouse_move_to(x, y);
+    }
+
+    fn press(&mut self) {
+        self.mouse_down(MouseButton::Left);
+    }
+
+    fn release(&mut self) {
+        self.mouse_up(MouseButton::Left);
+    }
+}
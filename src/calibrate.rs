@@ -0,0 +1,152 @@
+use crate::backend::PointerBackend;
+use crate::capture::ScreenCapture;
+use crate::colors::PaletteColor;
+use crate::coords::Coord;
+use crate::easel::{Easel, NUM_BRUSH_STEPS};
+use image::RgbaImage;
+use std::error::Error;
+use std::time::Duration;
+
+/// How long to give the game to show a stroke before capturing the easel.
+const SETTLE_TIME: Duration = Duration::from_millis(250);
+
+/// How far apart, summed over the color channels, a pixel has to move from the blank canvas to
+/// count as painted.
+const PAINTED_THRESHOLD: u32 = 96;
+
+/// Measures how many pixels wide the brush paints at every brush size, from 0 to
+/// `NUM_BRUSH_STEPS`. The result is meant to be stored as `EaselCoords::brush_widths`.
+///
+/// For each size, a black test stroke is drawn across the middle of the easel, the easel is
+/// captured and compared against how it looked beforehand, and the stroke is painted over in
+/// white again. The easel should be blank and white before starting.
+///
+/// # Example
+///
+/// ```no_run
+/// use enigo::Enigo;
+/// use passpartout_printer::{
+///     calibrate::calibrate_brushes,
+///     capture::{WithCapture, X11Capture},
+///     easel::Easel,
+/// };
+/// use std::error::Error;
+/// use std::time::Duration;
+///
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let backend = WithCapture {
+///     backend: Enigo::new(),
+///     capture: X11Capture::new()?,
+/// };
+/// let mut easel = Easel::new("coords.json".into(), backend, Duration::from_millis(7))?;
+/// easel.easel_coords.brush_widths = calibrate_brushes(&mut easel)?;
+/// easel.easel_coords.save("coords.json")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn calibrate_brushes<B: PointerBackend + ScreenCapture>(
+    easel: &mut Easel<B>,
+) -> Result<Vec<u32>, Box<dyn Error>> {
+    let (ul, lr) = easel.screen_bounds();
+    let size = &lr - &ul;
+    let start = Coord::new(size.x / 4, size.y / 2);
+    let end = Coord::new(size.x * 3 / 4, size.y / 2);
+
+    easel.mouse.sleep(SETTLE_TIME);
+    let mut blank = easel.mouse.capture(ul, lr)?;
+
+    let mut widths = vec![];
+    for brush_size in 0..=NUM_BRUSH_STEPS {
+        easel.change_brush_size(brush_size);
+        easel.draw_line(start, end, &PaletteColor::Black)?;
+        easel.mouse.sleep(SETTLE_TIME);
+        let painted = easel.mouse.capture(ul, lr)?;
+
+        let width = measure_width(&blank, &painted, start.x, end.x);
+        if width == 0 {
+            Err(format!(
+                "nothing was painted at brush size {}, check the easel coordinates",
+                brush_size
+            ))?
+        }
+        println!("-- brush size {}: {} pixels", brush_size, width);
+        widths.push(width);
+
+        // Paint over the test stroke and take a fresh look at the easel, in case painting over
+        // it didn't quite bring back the blank canvas.
+        easel.draw_line(start, end, &PaletteColor::White)?;
+        easel.mouse.sleep(SETTLE_TIME);
+        blank = easel.mouse.capture(ul, lr)?;
+    }
+
+    easel.change_brush_size(0);
+    easel.change_color(&PaletteColor::Black);
+    Ok(widths)
+}
+
+/// Measures the width of a horizontal stroke running from `start_x` to `end_x` by counting the
+/// painted pixels in a handful of columns along it. The median count is used so that the
+/// rounded ends of the stroke and any stray pixels don't throw the measurement off.
+fn measure_width(blank: &RgbaImage, painted: &RgbaImage, start_x: i32, end_x: i32) -> u32 {
+    let mut counts: Vec<u32> = (1..8)
+        .map(|step| (start_x + (end_x - start_x) * step / 8).max(0) as u32)
+        .filter(|&x| x < painted.width())
+        .map(|x| {
+            (0..painted.height())
+                .filter(|&y| {
+                    let before = blank.get_pixel(x, y);
+                    let after = painted.get_pixel(x, y);
+                    let difference: u32 = (0..3)
+                        .map(|c| (i32::from(before[c]) - i32::from(after[c])).unsigned_abs())
+                        .sum();
+                    difference > PAINTED_THRESHOLD
+                })
+                .count() as u32
+        })
+        .collect();
+    counts.sort_unstable();
+    counts.get(counts.len() / 2).copied().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulated::tests::coords;
+    use crate::simulated::SimulatedEasel;
+    use image::Rgba;
+
+    #[test]
+    fn measures_simulated_brushes() {
+        // The simulator paints round dabs centered on a pixel, which always come out an odd
+        // number of pixels across, so odd widths are measured exactly.
+        let mut coords = coords();
+        coords.brush_widths = (0..=NUM_BRUSH_STEPS as u32)
+            .map(|size| 2 * size + 1)
+            .collect();
+        let simulated = SimulatedEasel::new(&coords);
+        let mut easel = Easel::with_coords(coords.clone(), simulated, Duration::from_millis(7));
+
+        let widths = calibrate_brushes(&mut easel).unwrap();
+        assert_eq!(widths, coords.brush_widths);
+        assert_eq!(easel.brush_size, 0);
+    }
+
+    #[test]
+    fn measures_through_soft_edges() {
+        let blank = RgbaImage::from_pixel(40, 20, Rgba([255, 255, 255, 255]));
+        let mut painted = blank.clone();
+        for x in 0..30 {
+            for y in 8..=12 {
+                painted.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+            }
+            // A dark antialiased edge above the stroke counts, a faint one below doesn't.
+            painted.put_pixel(x, 7, Rgba([200, 200, 200, 255]));
+            painted.put_pixel(x, 13, Rgba([235, 235, 235, 255]));
+        }
+        // A stray pixel in one of the measured columns.
+        painted.put_pixel(10, 2, Rgba([0, 0, 0, 255]));
+
+        // The stroke stops short of the last measured column, which the median ignores.
+        assert_eq!(measure_width(&blank, &painted, 0, 40), 6);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::backend::PointerBackend;
+use crate::coords::Coord;
+use crate::error::PrinterError;
+use image::{Rgba, RgbaImage};
+use std::error::Error;
+use std::time::Duration;
+
+/// A source of screenshots of the game, used to see what has actually been painted on the easel.
+pub trait ScreenCapture {
+    /// Captures the region of the screen between the two corners, inclusive. Pixel (0, 0) of
+    /// the result is `upper_left`.
+    fn capture(
+        &mut self,
+        upper_left: Coord,
+        lower_right: Coord,
+    ) -> Result<RgbaImage, Box<dyn Error>>;
+}
+
+impl<C: ScreenCapture + ?Sized> ScreenCapture for Box<C> {
+    fn capture(
+        &mut self,
+        upper_left: Coord,
+        lower_right: Coord,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        (**self).capture(upper_left, lower_right)
+    }
+}
+
+/// Opens the best way of capturing the screen available on this platform.
+pub fn screen_capture() -> Result<Box<dyn ScreenCapture>, Box<dyn Error>> {
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Box::new(X11Capture::new()?))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        Err("capturing the screen isn't supported on this platform yet")?
+    }
+}
+
+/// Captures everything on the screen, for when it isn't known yet where the easel is.
+pub fn capture_screen() -> Result<RgbaImage, Box<dyn Error>> {
+    #[cfg(target_os = "linux")]
+    {
+        X11Capture::new()?.capture_screen()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        Err("capturing the screen isn't supported on this platform yet")?
+    }
+}
+
+/// Pairs a pointer backend with a screen capture, so an easel can both draw and look at what it
+/// has drawn.
+pub struct WithCapture<B: PointerBackend, C: ScreenCapture> {
+    /// The backend used for manipulating the mouse.
+    pub backend: B,
+
+    /// The source of screenshots.
+    pub capture: C,
+}
+
+impl<B: PointerBackend, C: ScreenCapture> PointerBackend for WithCapture<B, C> {
+    fn move_to(&mut self, x: i32, y: i32) {
+        self.backend.move_to(x, y);
+    }
+
+    fn press(&mut self) {
+        self.backend.press();
+    }
+
+    fn release(&mut self) {
+        self.backend.release();
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.backend.sleep(duration);
+    }
+}
+
+impl<B: PointerBackend, C: ScreenCapture> ScreenCapture for WithCapture<B, C> {
+    fn capture(
+        &mut self,
+        upper_left: Coord,
+        lower_right: Coord,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        self.capture.capture(upper_left, lower_right)
+    }
+}
+
+/// Captures from a screenshot saved to a file, such as one taken with the game's own screenshot
+/// key. Every capture is cut out of the same screenshot.
+pub struct FileCapture {
+    screenshot: RgbaImage,
+}
+
+impl FileCapture {
+    /// Loads a full-screen screenshot from an image file.
+    pub fn open(path: &str) -> Result<FileCapture, PrinterError> {
+        Ok(FileCapture {
+            screenshot: image::open(path)?.into_rgba8(),
+        })
+    }
+
+    /// Uses a screenshot that has already been loaded.
+    pub fn from_image(screenshot: RgbaImage) -> FileCapture {
+        FileCapture { screenshot }
+    }
+}
+
+impl ScreenCapture for FileCapture {
+    fn capture(
+        &mut self,
+        upper_left: Coord,
+        lower_right: Coord,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        let (width, height) = self.screenshot.dimensions();
+        if upper_left.x < 0
+            || upper_left.y < 0
+            || lower_right.x < upper_left.x
+            || lower_right.y < upper_left.y
+            || lower_right.x as u32 >= width
+            || lower_right.y as u32 >= height
+        {
+            Err(format!(
+                "the region {:?} to {:?} doesn't fit in the {}x{} screenshot",
+                upper_left, lower_right, width, height
+            ))?
+        }
+        let region = image::imageops::crop_imm(
+            &self.screenshot,
+            upper_left.x as u32,
+            upper_left.y as u32,
+            (lower_right.x - upper_left.x + 1) as u32,
+            (lower_right.y - upper_left.y + 1) as u32,
+        );
+        Ok(region.to_image())
+    }
+}
+
+/// Captures straight from the X server the game is displayed on.
+#[cfg(target_os = "linux")]
+pub struct X11Capture {
+    display: *mut x11::xlib::Display,
+}
+
+#[cfg(target_os = "linux")]
+impl X11Capture {
+    /// Connects to the X server named by the `DISPLAY` environment variable.
+    pub fn new() -> Result<X11Capture, Box<dyn Error>> {
+        let display = unsafe { x11::xlib::XOpenDisplay(std::ptr::null()) };
+        if display.is_null() {
+            Err("couldn't connect to the X server")?
+        }
+        Ok(X11Capture { display })
+    }
+
+    /// Captures the whole of the default screen.
+    pub fn capture_screen(&mut self) -> Result<RgbaImage, Box<dyn Error>> {
+        let (width, height) = unsafe {
+            let screen = x11::xlib::XDefaultScreen(self.display);
+            (
+                x11::xlib::XDisplayWidth(self.display, screen),
+                x11::xlib::XDisplayHeight(self.display, screen),
+            )
+        };
+        self.capture(Coord::new(0, 0), Coord::new(width - 1, height - 1))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl ScreenCapture for X11Capture {
+    fn capture(
+        &mut self,
+        upper_left: Coord,
+        lower_right: Coord,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        use x11::xlib;
+
+        let width = (lower_right.x - upper_left.x + 1).max(0) as u32;
+        let height = (lower_right.y - upper_left.y + 1).max(0) as u32;
+        if width == 0 || height == 0 {
+            Err(format!(
+                "the region {:?} to {:?} is empty",
+                upper_left, lower_right
+            ))?
+        }
+
+        unsafe {
+            let root = xlib::XDefaultRootWindow(self.display);
+            let ximage = xlib::XGetImage(
+                self.display,
+                root,
+                upper_left.x,
+                upper_left.y,
+                width,
+                height,
+                xlib::XAllPlanes(),
+                xlib::ZPixmap,
+            );
+            if ximage.is_null() {
+                Err(format!(
+                    "couldn't capture the region {:?} to {:?} of the screen",
+                    upper_left, lower_right
+                ))?
+            }
+
+            let masks = [
+                (*ximage).red_mask,
+                (*ximage).green_mask,
+                (*ximage).blue_mask,
+            ];
+            let image = RgbaImage::from_fn(width, height, |x, y| {
+                let pixel = xlib::XGetPixel(ximage, x as i32, y as i32);
+                let [r, g, b] = masks.map(|mask| channel(pixel, mask));
+                Rgba([r, g, b, 255])
+            });
+            xlib::XDestroyImage(ximage);
+            Ok(image)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for X11Capture {
+    fn drop(&mut self) {
+        unsafe {
+            x11::xlib::XCloseDisplay(self.display);
+        }
+    }
+}
+
+/// Pulls a single color channel out of a pixel, scaled to 8 bits.
+#[cfg(target_os = "linux")]
+fn channel(pixel: std::os::raw::c_ulong, mask: std::os::raw::c_ulong) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let max = mask >> mask.trailing_zeros();
+    let value = (pixel & mask) >> mask.trailing_zeros();
+    (value * 255 / max) as u8
+}
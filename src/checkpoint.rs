@@ -0,0 +1,318 @@
+use crate::backend::PointerBackend;
+use crate::colors::PaletteColor;
+use crate::easel::{Easel, Orientation, Tool};
+use crate::error::PrinterError;
+use crate::plan::{DrawOp, DrawPlan};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// A record of how far a drawing has progressed, so an interrupted drawing can pick up where it
+/// left off instead of starting from scratch.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    /// A hash of the plan being drawn. Since the plan is built from the image and the settings
+    /// used to prepare it, a change to either gives a different hash.
+    pub plan_hash: u64,
+
+    /// The image or plan file the drawing was started from.
+    pub source: String,
+
+    /// The mouse wait, in milliseconds, the drawing was started with. It doesn't change what's
+    /// drawn, so it isn't part of the plan hash, but a resumed drawing goes back to it unless
+    /// another wait is asked for.
+    pub mouse_wait: u64,
+
+    /// The index of the first operation of the plan that hasn't been completed yet.
+    pub next_op: usize,
+
+    /// The active color of the brush when the checkpoint was taken.
+    pub color: PaletteColor,
+
+    /// The brush size when the checkpoint was taken.
+    pub brush_size: i32,
+
+    /// The orientation of the easel when the checkpoint was taken.
+    pub orientation: Orientation,
+
+    /// The active tool when the checkpoint was taken.
+    pub tool: Tool,
+}
+
+impl Checkpoint {
+    /// Captures the current state of the easel, noting that every operation before `next_op`
+    /// has been drawn.
+    pub fn capture<B: PointerBackend>(
+        plan_hash: u64,
+        source: &str,
+        next_op: usize,
+        easel: &Easel<B>,
+    ) -> Checkpoint {
+        Checkpoint {
+            plan_hash,
+            source: source.to_string(),
+            mouse_wait: easel.mouse_wait.as_millis() as u64,
+            next_op,
+            color: easel.current_color,
+            brush_size: easel.brush_size,
+            orientation: easel.orientation,
+            tool: easel.current_tool,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Checkpoint, PrinterError> {
+        let mut file = File::open(path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let checkpoint: Checkpoint = serde_json::from_str(&contents).map_err(io::Error::from)?;
+        Ok(checkpoint)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), PrinterError> {
+        let j = serde_json::to_string_pretty(self).map_err(io::Error::from)?;
+        let mut file = File::create(path)?;
+        file.write_all(j.as_bytes())?;
+        Ok(())
+    }
+
+    /// Brings the easel back to the state it was in when the checkpoint was taken.
+    ///
+    /// The orientation can't be changed without wiping the drawing in progress, so the easel is
+    /// assumed to still be in the orientation it was left in and is only told about it.
+    pub fn restore<B: PointerBackend>(&self, easel: &mut Easel<B>) {
+        easel.orientation = self.orientation;
+        easel.change_tool(self.tool);
+        easel.change_brush_size(self.brush_size);
+        easel.change_color(&self.color);
+    }
+}
+
+/// Periodically saves checkpoints to a file while a plan is being drawn.
+pub struct Checkpointer {
+    path: String,
+    source: String,
+    plan_hash: u64,
+    interval: Duration,
+    last_save: Instant,
+}
+
+impl Checkpointer {
+    /// Creates a checkpointer that saves to `path` at most once every `interval`.
+    pub fn new(path: &str, source: &str, plan: &DrawPlan, interval: Duration) -> Checkpointer {
+        Checkpointer {
+            path: path.to_string(),
+            source: source.to_string(),
+            plan_hash: plan_hash(plan),
+            interval,
+            last_save: Instant::now(),
+        }
+    }
+
+    /// Loads the checkpoint from the file and checks that it belongs to the plan being drawn.
+    pub fn load(&self) -> Result<Checkpoint, PrinterError> {
+        let checkpoint = Checkpoint::load(&self.path)?;
+        if checkpoint.plan_hash != self.plan_hash {
+            Err(PrinterError::InvalidPlan(format!(
+                "{} was saved while drawing {} with different settings",
+                self.path, checkpoint.source
+            )))?
+        }
+        Ok(checkpoint)
+    }
+
+    /// Saves a checkpoint if enough time has passed since the last one.
+    pub fn update<B: PointerBackend>(
+        &mut self,
+        next_op: usize,
+        easel: &Easel<B>,
+    ) -> Result<(), PrinterError> {
+        if self.last_save.elapsed() >= self.interval {
+            self.save(next_op, easel)?;
+        }
+        Ok(())
+    }
+
+    /// Carries out the operation at `index` of the plan, first saving a checkpoint if one is
+    /// due. If the operation fails, or panics, a checkpoint is saved straight away so the
+    /// drawing can be resumed from that operation without losing any progress. The panic is
+    /// then carried on as usual.
+    pub fn execute<B: PointerBackend>(
+        &mut self,
+        index: usize,
+        op: &DrawOp,
+        easel: &mut Easel<B>,
+    ) -> Result<(), PrinterError> {
+        self.update(index, easel)?;
+        match panic::catch_unwind(AssertUnwindSafe(|| easel.execute(op))) {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => {
+                self.save(index, easel)?;
+                Err(e)
+            }
+            Err(cause) => {
+                // Saving can't be allowed to hide the panic, so a failure here is dropped.
+                let _ = self.save(index, easel);
+                panic::resume_unwind(cause)
+            }
+        }
+    }
+
+    /// Carries out every operation of the plan from `start_op` on, saving checkpoints along the
+    /// way as `execute` does.
+    pub fn execute_plan<B: PointerBackend>(
+        &mut self,
+        easel: &mut Easel<B>,
+        plan: &DrawPlan,
+        start_op: usize,
+    ) -> Result<(), PrinterError> {
+        for (index, op) in plan.ops.iter().enumerate().skip(start_op) {
+            self.execute(index, op, easel)?;
+        }
+        Ok(())
+    }
+
+    /// Saves a checkpoint right away.
+    pub fn save<B: PointerBackend>(
+        &mut self,
+        next_op: usize,
+        easel: &Easel<B>,
+    ) -> Result<(), PrinterError> {
+        Checkpoint::capture(self.plan_hash, &self.source, next_op, easel).save(&self.path)?;
+        self.last_save = Instant::now();
+        Ok(())
+    }
+
+    /// Removes the checkpoint file once the drawing is finished.
+    pub fn finish(self) -> Result<(), PrinterError> {
+        if Path::new(&self.path).exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Computes a hash of a plan that stays the same across runs and machines.
+pub fn plan_hash(plan: &DrawPlan) -> u64 {
+    // FNV-1a over the serialized plan. The standard library's hashers aren't guaranteed to
+    // give the same result between releases, which would invalidate old checkpoints.
+    let bytes = serde_json::to_vec(plan).unwrap_or_default();
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::PaletteColor;
+    use crate::coords::Coord;
+    use crate::simulated::{tests::coords, SimulatedEasel};
+    use crate::window::WindowProvider;
+    use std::error::Error;
+    use std::time::Duration;
+
+    /// A window that panics the given number of lookups in, standing in for anything going
+    /// badly wrong partway through a drawing.
+    struct PanickingWindow(usize);
+
+    impl WindowProvider for PanickingWindow {
+        fn origin(&mut self) -> Result<Coord, Box<dyn Error>> {
+            self.0 -= 1;
+            if self.0 == 0 {
+                panic!("the window went away");
+            }
+            Ok(Coord::new(0, 0))
+        }
+    }
+
+    /// A plan of short horizontal strokes, one on each of the given rows.
+    fn plan(rows: i32) -> DrawPlan {
+        let mut plan = DrawPlan::new();
+        plan.push(DrawOp::SetColor(PaletteColor::Red));
+        for y in 0..rows {
+            plan.push(DrawOp::Stroke(vec![
+                Coord::new(0, y * 10),
+                Coord::new(20, y * 10),
+            ]));
+        }
+        plan
+    }
+
+    fn checkpoint_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "passpartout_printer_{}_{}.json",
+            name,
+            std::process::id()
+        ));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn a_panic_saves_the_operation_it_happened_in() {
+        let path = checkpoint_path("panic");
+        let plan = plan(6);
+        let coords = coords();
+
+        // One lookup when the easel is made, then one before each operation, so the fifth
+        // lookup panics in the fourth operation.
+        let window = Box::new(PanickingWindow(5));
+        let mouse = SimulatedEasel::new(&coords);
+        let wait = Duration::from_millis(7);
+        let mut easel = Easel::with_window(coords, mouse, wait, window).unwrap();
+        let mut checkpointer = Checkpointer::new(&path, "test", &plan, Duration::from_secs(60));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            checkpointer.execute_plan(&mut easel, &plan, 0)
+        }));
+        assert!(result.is_err());
+
+        let checkpoint = checkpointer.load().unwrap();
+        assert_eq!(checkpoint.next_op, 3);
+        assert_eq!(checkpoint.color, PaletteColor::Red);
+        assert_eq!(checkpoint.mouse_wait, 7);
+        checkpointer.finish().unwrap();
+    }
+
+    #[test]
+    fn resuming_skips_exactly_the_finished_operations() {
+        let path = checkpoint_path("resume");
+        let plan = plan(6);
+        let coords = coords();
+        let mut checkpointer = Checkpointer::new(&path, "test", &plan, Duration::from_secs(60));
+
+        // Stop the first run partway through by making the fifth operation fail.
+        let mut broken = plan.clone();
+        broken.ops[4] = DrawOp::Stroke(vec![Coord::new(0, 1000)]);
+        let mut first = Easel::with_coords(
+            coords.clone(),
+            SimulatedEasel::new(&coords),
+            Duration::from_millis(7),
+        );
+        assert!(checkpointer.execute_plan(&mut first, &broken, 0).is_err());
+
+        let checkpoint = checkpointer.load().unwrap();
+        assert_eq!(checkpoint.next_op, 4);
+
+        let mut resumed = Easel::with_coords(
+            coords.clone(),
+            SimulatedEasel::new(&coords),
+            Duration::from_millis(7),
+        );
+        checkpoint.restore(&mut resumed);
+        let strokes = resumed.mouse.estimate().strokes;
+        checkpointer
+            .execute_plan(&mut resumed, &plan, checkpoint.next_op)
+            .unwrap();
+
+        // Only the strokes on rows 3, 4 and 5 are drawn again.
+        assert_eq!(resumed.mouse.estimate().strokes - strokes, 3);
+        let canvas = resumed.mouse.canvas();
+        let red = PaletteColor::Red.get_rgba();
+        for row in 0..6 {
+            assert_eq!(*canvas.get_pixel(10, row * 10) == red, row >= 3);
+        }
+        checkpointer.finish().unwrap();
+    }
+}
@@ -1,9 +1,13 @@
 use crate::coords::Coord;
 use image::imageops::colorops::ColorMap;
 use image::Rgba;
+use serde::{Deserialize, Serialize};
 use std::f32;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 pub enum PaletteColor {
     Black,
     Grey,
@@ -31,13 +35,191 @@ pub enum PaletteColor {
     LightViolet,
 }
 
-#[derive(Default)]
+/// One color of a palette: which color it is, how it looks on screen and where it sits in the
+/// palette grid.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct PaletteEntry {
+    /// Which of the game's colors this is. A palette can only describe the game's own colors,
+    /// so there's no way to add new ones.
+    pub name: PaletteColor,
+    pub rgba: [u8; 4],
+    pub row: u32,
+    pub col: u32,
+}
+
+/// The colors of the palette in use, the game's own unless `use_palette` picked others.
+static IN_USE: OnceLock<InUse> = OnceLock::new();
+
+/// A palette picked for the run, along with every color's entry indexed by the color so they
+/// can be looked up quickly.
+struct InUse {
+    entries: Vec<PaletteEntry>,
+
+    // The entry for each color, in the order of `PaletteColor::ALL`. Colors the palette leaves
+    // out keep the game's values.
+    by_color: [PaletteEntry; 24],
+}
+
+impl InUse {
+    fn new(entries: Vec<PaletteEntry>) -> InUse {
+        let mut by_color = PaletteColor::ALL.map(|color| color.default_entry());
+        for entry in entries.iter() {
+            by_color[entry.name as usize] = *entry;
+        }
+        InUse { entries, by_color }
+    }
+}
+
+/// Settles on the game's palette if no other was picked.
+fn in_use() -> &'static InUse {
+    IN_USE.get_or_init(|| {
+        InUse::new(
+            PaletteColor::ALL
+                .iter()
+                .map(PaletteColor::default_entry)
+                .collect(),
+        )
+    })
+}
+
+/// The colors of the palette in use, settling on the game's palette if no other was picked.
+pub fn palette_in_use() -> &'static [PaletteEntry] {
+    &in_use().entries
+}
+
+/// Uses the given colors instead of the game's palette for the rest of the run, such as ones
+/// read with `config::load_palette`. Images are only matched to the colors given, in the order
+/// given, while colors left out keep the game's values should a plan ask for them. The
+/// palette can't be changed once any color has been used, since matches to it are kept for
+/// the rest of the run.
+pub fn use_palette(colors: Vec<PaletteEntry>) -> Result<(), String> {
+    IN_USE
+        .set(InUse::new(colors))
+        .map_err(|_| "the palette can't be changed once colors are in use".to_string())
+}
+
+/// The ways of measuring how different two colors are, used to find the closest palette color.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ColorMetric {
+    /// The straight-line distance between the red, green and blue values, with the alpha
+    /// folded into each channel.
+    #[default]
+    Rgb,
+
+    /// The RGB distance weighted by how red the colors are, a cheap approximation of how the
+    /// eye sees differences.
+    Redmean,
+
+    /// The straight-line distance in CIELAB, known as CIE76 or ΔE*76.
+    Cie76,
+
+    /// The CIEDE2000 color difference, the most faithful to the eye and the slowest.
+    Ciede2000,
+}
+
+impl FromStr for ColorMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ColorMetric, String> {
+        match s {
+            "rgb" => Ok(ColorMetric::Rgb),
+            "redmean" => Ok(ColorMetric::Redmean),
+            "cie76" => Ok(ColorMetric::Cie76),
+            "ciede2000" => Ok(ColorMetric::Ciede2000),
+            _ => Err(format!("unknown color metric '{}'", s)),
+        }
+    }
+}
+
+/// The colors of the game's palette, along with the means to match any color to the closest
+/// one.
+///
+/// Matches for opaque colors are kept in a lookup table for each metric, filled in as colors
+/// are first seen and shared by every palette, so quantizing or dithering an image only works
+/// out each distinct color once. The table assumes the palette in use, so `colormap` shouldn't
+/// be changed.
 pub struct Palette {
     pub colormap: Vec<PaletteColor>,
+
+    /// How the distance between colors is measured.
+    pub metric: ColorMetric,
+
+    // The palette colors as they look on screen, and in CIELAB, worked out once up front.
+    rgba: Vec<Rgba<u8>>,
+    lab: Vec<[f32; 3]>,
+}
+
+/// Marks a color in a lookup table that hasn't been matched yet.
+const UNMATCHED: u8 = u8::MAX;
+
+/// The lookup tables of closest palette colors, one for each `ColorMetric`, indexed by the
+/// red, green and blue values of a color packed into 24 bits.
+static MATCHES: [OnceLock<Box<[AtomicU8]>>; 4] = [
+    OnceLock::new(),
+    OnceLock::new(),
+    OnceLock::new(),
+    OnceLock::new(),
+];
+
+/// Finds the lookup table for the given metric, creating it empty the first time it's needed.
+fn match_table(metric: ColorMetric) -> &'static [AtomicU8] {
+    MATCHES[metric as usize]
+        .get_or_init(|| (0..1 << 24).map(|_| AtomicU8::new(UNMATCHED)).collect())
 }
 
 impl PaletteColor {
+    /// Every color of the game's palette, in the order of the palette grid.
+    pub const ALL: [PaletteColor; 24] = [
+        PaletteColor::Black,
+        PaletteColor::Grey,
+        PaletteColor::White,
+        PaletteColor::DarkBrown,
+        PaletteColor::Brown,
+        PaletteColor::LightBrown,
+        PaletteColor::DarkRed,
+        PaletteColor::Red,
+        PaletteColor::Pink,
+        PaletteColor::Orange,
+        PaletteColor::DarkYellow,
+        PaletteColor::Yellow,
+        PaletteColor::DarkGreen,
+        PaletteColor::Green,
+        PaletteColor::LightGreen,
+        PaletteColor::DarkBlue,
+        PaletteColor::Blue,
+        PaletteColor::LightBlue,
+        PaletteColor::DarkIndigo,
+        PaletteColor::Indigo,
+        PaletteColor::LightIndigo,
+        PaletteColor::DarkViolet,
+        PaletteColor::Violet,
+        PaletteColor::LightViolet,
+    ];
+
+    /// The color as it's described in the palette in use, or in the game's palette if the
+    /// palette in use leaves it out.
+    fn entry(&self) -> PaletteEntry {
+        in_use().by_color[*self as usize]
+    }
+
+    /// The color as it is in the game's palette.
+    pub fn default_entry(&self) -> PaletteEntry {
+        let pos = self.default_row_col();
+        PaletteEntry {
+            name: *self,
+            rgba: self.default_rgba().0,
+            row: pos.x as u32,
+            col: pos.y as u32,
+        }
+    }
+
+    /// The row and column of the color in the palette grid.
     pub fn get_row_col(&self) -> Coord {
+        let entry = self.entry();
+        Coord::new(entry.row as i32, entry.col as i32)
+    }
+
+    fn default_row_col(&self) -> Coord {
         Coord::from(match *self {
             PaletteColor::Black => (0, 0),
             PaletteColor::Grey => (0, 1),
@@ -66,7 +248,26 @@ impl PaletteColor {
         })
     }
 
+    /// Finds the color at the given position in the palette grid, if there is one.
+    pub fn from_row_col(pos: Coord) -> Option<PaletteColor> {
+        palette_in_use()
+            .iter()
+            .find(|entry| entry.row as i32 == pos.x && entry.col as i32 == pos.y)
+            .map(|entry| entry.name)
+    }
+
+    /// The perceived brightness of the color, from 0 for black to 255 for white.
+    pub fn lightness(&self) -> f32 {
+        let rgba = self.get_rgba();
+        0.299 * f32::from(rgba[0]) + 0.587 * f32::from(rgba[1]) + 0.114 * f32::from(rgba[2])
+    }
+
+    /// The color as it looks on screen.
     pub fn get_rgba(&self) -> Rgba<u8> {
+        Rgba(self.entry().rgba)
+    }
+
+    fn default_rgba(&self) -> Rgba<u8> {
         let data = match *self {
             PaletteColor::Black => [0x0d, 0x0d, 0x0d, 0xff],
             PaletteColor::Grey => [0x76, 0x76, 0x76, 0xff],
@@ -97,51 +298,103 @@ impl PaletteColor {
     }
 }
 
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::new()
+    }
+}
+
 impl Palette {
+    /// Creates the palette in use, matching colors with `ColorMetric::Rgb`.
     pub fn new() -> Palette {
-        let colors = vec![
-            PaletteColor::Black,
-            PaletteColor::Grey,
-            PaletteColor::White,
-            PaletteColor::DarkBrown,
-            PaletteColor::Brown,
-            PaletteColor::LightBrown,
-            PaletteColor::DarkRed,
-            PaletteColor::Red,
-            PaletteColor::Pink,
-            PaletteColor::Orange,
-            PaletteColor::DarkYellow,
-            PaletteColor::Yellow,
-            PaletteColor::DarkGreen,
-            PaletteColor::Green,
-            PaletteColor::LightGreen,
-            PaletteColor::DarkBlue,
-            PaletteColor::Blue,
-            PaletteColor::LightBlue,
-            PaletteColor::DarkIndigo,
-            PaletteColor::Indigo,
-            PaletteColor::LightIndigo,
-            PaletteColor::DarkViolet,
-            PaletteColor::Violet,
-            PaletteColor::LightViolet,
-        ];
-        Palette { colormap: colors }
+        Palette::with_metric(ColorMetric::default())
+    }
+
+    /// Creates the palette in use, matching colors with the given metric.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use image::{imageops::ColorMap, Rgba};
+    /// use passpartout_printer::colors::{ColorMetric, Palette, PaletteColor};
+    ///
+    /// let palette = Palette::with_metric(ColorMetric::Ciede2000);
+    /// let index = palette.index_of(&Rgba([0xea, 0x20, 0x25, 0xff]));
+    /// assert_eq!(palette.colormap[index], PaletteColor::Red);
+    /// ```
+    pub fn with_metric(metric: ColorMetric) -> Palette {
+        let colors = palette_in_use();
+        let rgba: Vec<Rgba<u8>> = colors.iter().map(|entry| Rgba(entry.rgba)).collect();
+        let lab = rgba.iter().map(to_lab).collect();
+        Palette {
+            colormap: colors.iter().map(|entry| entry.name).collect(),
+            metric,
+            rgba,
+            lab,
+        }
     }
 
     fn get_closest_color(&self, color: &Rgba<u8>) -> (usize, Rgba<u8>) {
+        let index = self.lookup(color);
+        (index, self.rgba[index])
+    }
+
+    /// Finds the index of the closest palette color in the lookup table, matching the color
+    /// and filling in the table if it hasn't been seen before. Colors that aren't opaque are
+    /// rare enough that they're always matched from scratch.
+    fn lookup(&self, color: &Rgba<u8>) -> usize {
+        if color[3] != 0xff {
+            return self.closest_index(color);
+        }
+        let key =
+            (usize::from(color[0]) << 16) | (usize::from(color[1]) << 8) | usize::from(color[2]);
+        let entry = &match_table(self.metric)[key];
+        match entry.load(Ordering::Relaxed) {
+            UNMATCHED => {
+                let index = self.closest_index(color);
+                entry.store(index as u8, Ordering::Relaxed);
+                index
+            }
+            index => usize::from(index),
+        }
+    }
+
+    /// Finds the index of the closest palette color by comparing against every one of them.
+    fn closest_index(&self, color: &Rgba<u8>) -> usize {
+        let distances: Vec<f32> = match self.metric {
+            ColorMetric::Rgb => return self.closest_rgb(color),
+            ColorMetric::Redmean => {
+                let color = over_canvas(color);
+                self.rgba.iter().map(|col| redmean(&color, col)).collect()
+            }
+            ColorMetric::Cie76 => {
+                let color = to_lab(&over_canvas(color));
+                self.lab.iter().map(|lab| cie76(&color, lab)).collect()
+            }
+            ColorMetric::Ciede2000 => {
+                let color = to_lab(&over_canvas(color));
+                self.lab.iter().map(|lab| ciede2000(&color, lab)).collect()
+            }
+        };
+        distances
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(b.1))
+            .map_or(0, |(index, _)| index)
+    }
+
+    fn closest_rgb(&self, color: &Rgba<u8>) -> usize {
         let r = f32::from(color[0]);
         let g = f32::from(color[1]);
         let b = f32::from(color[2]);
         let a = f32::from(color[3]);
 
         let mut index = 0;
-        let mut closest_color = PaletteColor::Black;
         let mut color_dist = f32::MAX;
 
         // Iterate over all colors and compare the RBG values to find the
         // closest value to the input color.
-        for (ix, col) in self.colormap.iter().enumerate() {
-            let hex = col.get_rgba();
+        for (ix, hex) in self.rgba.iter().enumerate() {
             let col_r = f32::from(hex[0]);
             let col_g = f32::from(hex[1]);
             let col_b = f32::from(hex[2]);
@@ -152,14 +405,133 @@ impl Palette {
             let curr_color_dist = (col_r_diff + col_g_diff + col_b_diff).sqrt();
             if curr_color_dist < color_dist {
                 index = ix;
-                closest_color = *col;
                 color_dist = curr_color_dist;
             }
         }
-        (index, closest_color.get_rgba())
+        index
     }
 }
 
+/// Blends a color over the white of a blank canvas by its alpha, since that's how a
+/// see-through pixel would look once drawn.
+fn over_canvas(color: &Rgba<u8>) -> Rgba<u8> {
+    let alpha = f32::from(color[3]) / 255.0;
+    let canvas = PaletteColor::White.get_rgba();
+    let mut blended = [0xff; 4];
+    for c in 0..3 {
+        let value = f32::from(color[c]) * alpha + f32::from(canvas[c]) * (1.0 - alpha);
+        blended[c] = value.round() as u8;
+    }
+    Rgba(blended)
+}
+
+/// The squared "redmean" distance between two colors, which weights each channel by how red
+/// the colors are.
+fn redmean(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    let rmean = (f32::from(a[0]) + f32::from(b[0])) / 2.0;
+    let dr = f32::from(a[0]) - f32::from(b[0]);
+    let dg = f32::from(a[1]) - f32::from(b[1]);
+    let db = f32::from(a[2]) - f32::from(b[2]);
+    (2.0 + rmean / 256.0) * dr * dr + 4.0 * dg * dg + (2.0 + (255.0 - rmean) / 256.0) * db * db
+}
+
+/// Converts an sRGB color to CIELAB under the D65 white point, ignoring alpha.
+fn to_lab(color: &Rgba<u8>) -> [f32; 3] {
+    let linear = |c: u8| {
+        let c = f32::from(c) / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let (r, g, b) = (linear(color[0]), linear(color[1]), linear(color[2]));
+    let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+    let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+
+    let f = |t: f32| {
+        if t > 0.008_856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// The squared CIE76 distance between two colors in CIELAB.
+fn cie76(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    (0..3).map(|i| (a[i] - b[i]).powi(2)).sum()
+}
+
+/// The CIEDE2000 color difference between two colors in CIELAB, following Sharma, Wu and
+/// Dalal's "The CIEDE2000 Color-Difference Formula".
+fn ciede2000(lab1: &[f32; 3], lab2: &[f32; 3]) -> f32 {
+    let [l1, a1, b1] = *lab1;
+    let [l2, a2, b2] = *lab2;
+
+    let c1 = (a1 * a1 + b1 * b1).sqrt();
+    let c2 = (a2 * a2 + b2 * b2).sqrt();
+    let c_mean7 = ((c1 + c2) / 2.0).powi(7);
+    let g = 0.5 * (1.0 - (c_mean7 / (c_mean7 + 25f32.powi(7))).sqrt());
+    let a1 = a1 * (1.0 + g);
+    let a2 = a2 * (1.0 + g);
+    let c1 = (a1 * a1 + b1 * b1).sqrt();
+    let c2 = (a2 * a2 + b2 * b2).sqrt();
+    let hue = |b: f32, a: f32| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let h1 = hue(b1, a1);
+    let h2 = hue(b2, a2);
+
+    let dl = l2 - l1;
+    let dc = c2 - c1;
+    let dh = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 - h1 > 180.0 {
+        h2 - h1 - 360.0
+    } else {
+        h2 - h1 + 360.0
+    };
+    let dh = 2.0 * (c1 * c2).sqrt() * (dh / 2.0).to_radians().sin();
+
+    let l_mean = (l1 + l2) / 2.0;
+    let c_mean = (c1 + c2) / 2.0;
+    let h_mean = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_mean - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_mean).to_radians().cos()
+        + 0.32 * (3.0 * h_mean + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_mean - 63.0).to_radians().cos();
+    let d_theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+    let c_mean7 = c_mean.powi(7);
+    let r_c = 2.0 * (c_mean7 / (c_mean7 + 25f32.powi(7))).sqrt();
+    let l50 = (l_mean - 50.0).powi(2);
+    let s_l = 1.0 + 0.015 * l50 / (20.0 + l50).sqrt();
+    let s_c = 1.0 + 0.045 * c_mean;
+    let s_h = 1.0 + 0.015 * c_mean * t;
+    let r_t = -(2.0 * d_theta).to_radians().sin() * r_c;
+
+    let (dl, dc, dh) = (dl / s_l, dc / s_c, dh / s_h);
+    (dl * dl + dc * dc + dh * dh + r_t * dc * dh).sqrt()
+}
+
 impl ColorMap for Palette {
     type Color = Rgba<u8>;
 
@@ -176,3 +548,159 @@ impl ColorMap for Palette {
         index
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The test data from Sharma, Wu and Dalal: pairs of CIELAB colors and the CIEDE2000
+    /// difference between them, given to four decimal places.
+    const SHARMA: [([f32; 3], [f32; 3], f32); 34] = [
+        ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+        ([50.0, 3.1571, -77.2803], [50.0, 0.0, -82.7485], 2.8615),
+        ([50.0, 2.8361, -74.0200], [50.0, 0.0, -82.7485], 3.4412),
+        ([50.0, -1.3802, -84.2814], [50.0, 0.0, -82.7485], 1.0000),
+        ([50.0, -1.1848, -84.8006], [50.0, 0.0, -82.7485], 1.0000),
+        ([50.0, -0.9009, -85.5211], [50.0, 0.0, -82.7485], 1.0000),
+        ([50.0, 0.0, 0.0], [50.0, -1.0, 2.0], 2.3669),
+        ([50.0, -1.0, 2.0], [50.0, 0.0, 0.0], 2.3669),
+        ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0009], 7.1792),
+        ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0010], 7.1792),
+        ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0011], 7.2195),
+        ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0012], 7.2195),
+        ([50.0, -0.001, 2.49], [50.0, 0.0009, -2.49], 4.8045),
+        ([50.0, -0.001, 2.49], [50.0, 0.0010, -2.49], 4.8045),
+        ([50.0, -0.001, 2.49], [50.0, 0.0011, -2.49], 4.7461),
+        ([50.0, 2.5, 0.0], [50.0, 0.0, -2.5], 4.3065),
+        ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+        ([50.0, 2.5, 0.0], [61.0, -5.0, 29.0], 22.8977),
+        ([50.0, 2.5, 0.0], [56.0, -27.0, -3.0], 31.9030),
+        ([50.0, 2.5, 0.0], [58.0, 24.0, 15.0], 19.4535),
+        ([50.0, 2.5, 0.0], [50.0, 3.1736, 0.5854], 1.0000),
+        ([50.0, 2.5, 0.0], [50.0, 3.2972, 0.0], 1.0000),
+        ([50.0, 2.5, 0.0], [50.0, 1.8634, 0.5757], 1.0000),
+        ([50.0, 2.5, 0.0], [50.0, 3.2592, 0.3350], 1.0000),
+        (
+            [60.2574, -34.0099, 36.2677],
+            [60.4626, -34.1751, 39.4387],
+            1.2644,
+        ),
+        (
+            [63.0109, -31.0961, -5.8663],
+            [62.8187, -29.7946, -4.0864],
+            1.2630,
+        ),
+        (
+            [61.2901, 3.7196, -5.3901],
+            [61.4292, 2.2480, -4.9620],
+            1.8731,
+        ),
+        (
+            [35.0831, -44.1164, 3.7933],
+            [35.0232, -40.0716, 1.5901],
+            1.8645,
+        ),
+        (
+            [22.7233, 20.0904, -46.6940],
+            [23.0331, 14.9730, -42.5619],
+            2.0373,
+        ),
+        (
+            [36.4612, 47.8580, 18.3852],
+            [36.2715, 50.5065, 21.2231],
+            1.4146,
+        ),
+        (
+            [90.8027, -2.0831, 1.4410],
+            [91.1528, -1.6435, 0.0447],
+            1.4441,
+        ),
+        (
+            [90.9257, -0.5406, -0.9208],
+            [88.6381, -0.8985, -0.7239],
+            1.5381,
+        ),
+        (
+            [6.7747, -0.2908, -2.4247],
+            [5.8714, -0.0985, -2.2286],
+            0.6377,
+        ),
+        (
+            [2.0776, 0.0795, -1.1350],
+            [0.9033, -0.0636, -0.5514],
+            0.9082,
+        ),
+    ];
+
+    const METRICS: [ColorMetric; 4] = [
+        ColorMetric::Rgb,
+        ColorMetric::Redmean,
+        ColorMetric::Cie76,
+        ColorMetric::Ciede2000,
+    ];
+
+    #[test]
+    fn ciede2000_matches_the_reference_data() {
+        for (index, (lab1, lab2, expected)) in SHARMA.iter().enumerate() {
+            for (a, b) in [(lab1, lab2), (lab2, lab1)] {
+                let difference = ciede2000(a, b);
+                assert!(
+                    (difference - expected).abs() < 1e-4,
+                    "pair {}: {} instead of {}",
+                    index + 1,
+                    difference,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ciede2000_of_a_color_with_itself_is_zero() {
+        for (lab, _, _) in SHARMA {
+            assert_eq!(ciede2000(&lab, &lab), 0.0);
+        }
+    }
+
+    #[test]
+    fn the_lookup_table_agrees_with_matching_from_scratch() {
+        for metric in METRICS {
+            let palette = Palette::with_metric(metric);
+            for r in (0..=255).step_by(15) {
+                for g in (0..=255).step_by(15) {
+                    for b in (0..=255).step_by(15) {
+                        let color = Rgba([r, g, b, 0xff]);
+                        let expected = palette.closest_index(&color);
+                        // Once to fill in the table, and again to read it back.
+                        assert_eq!(palette.lookup(&color), expected, "{:?}", color);
+                        assert_eq!(palette.index_of(&color), expected, "{:?}", color);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn colors_that_arent_opaque_skip_the_lookup_table() {
+        let palette = Palette::with_metric(ColorMetric::Cie76);
+        let black = PaletteColor::Black.get_rgba();
+        let faint = Rgba([black[0], black[1], black[2], 0x10]);
+        let index = palette.index_of(&faint);
+        assert_eq!(index, palette.closest_index(&faint));
+        assert_ne!(palette.colormap[index], PaletteColor::Black);
+        assert_eq!(
+            palette.colormap[palette.index_of(&black)],
+            PaletteColor::Black
+        );
+    }
+
+    #[test]
+    fn every_metric_matches_palette_colors_to_themselves() {
+        for metric in METRICS {
+            let palette = Palette::with_metric(metric);
+            for (index, color) in palette.rgba.iter().enumerate() {
+                assert_eq!(palette.index_of(color), index, "{:?}", metric);
+            }
+        }
+    }
+}
@@ -0,0 +1,543 @@
+use crate::colors::{PaletteColor, PaletteEntry};
+use crate::easel::{EaselCoords, BRUSH_WIDTHS};
+use crate::error::PrinterError;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// The version of the configuration layout written by this version of the application.
+/// Configurations without a version are taken to be version 1, the layout used before versions
+/// were added.
+pub const CONFIG_VERSION: u32 = 2;
+
+/// The formats a configuration can be written in, picked by the file's extension.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Picks the format for a path: TOML for ".toml" files and JSON for everything else.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use passpartout_printer::config::ConfigFormat;
+    ///
+    /// assert_eq!(ConfigFormat::from_path("coords.toml"), ConfigFormat::Toml);
+    /// assert_eq!(ConfigFormat::from_path("coords.json"), ConfigFormat::Json);
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> ConfigFormat {
+        match path.as_ref().extension() {
+            Some(ext) if ext == "toml" => ConfigFormat::Toml,
+            _ => ConfigFormat::Json,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A list of potential errors that can occur while reading a configuration.
+pub enum ConfigError {
+    /// The file couldn't be read.
+    Read { path: String, message: String },
+
+    /// The file isn't valid JSON or TOML at all.
+    Syntax { path: String, message: String },
+
+    /// The version field isn't a number.
+    BadVersion { path: String },
+
+    /// The configuration was written by a newer version of the application.
+    TooNew { path: String, version: u32 },
+
+    /// A field is missing or holds the wrong kind of value.
+    Field {
+        path: String,
+        field: String,
+        message: String,
+    },
+
+    /// The configuration couldn't be written out in the format asked for.
+    Write { path: String, message: String },
+
+    /// The palette file couldn't be read.
+    ReadPalette { path: String, message: String },
+
+    /// The settings asked for don't make sense, or no configuration could be worked out from
+    /// them.
+    Invalid(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read { path, message } => write!(
+                f,
+                "{}: couldn't read the configuration, run --configure to make one: {}",
+                path, message
+            ),
+            ConfigError::Syntax { path, message } => write!(f, "{}: {}", path, message),
+            ConfigError::BadVersion { path } => {
+                write!(f, "{}: version: expected a whole number", path)
+            }
+            ConfigError::TooNew { path, version } => write!(
+                f,
+                "{}: the configuration is version {} but only versions up to {} are \
+                 understood, try a newer passpartout_printer",
+                path, version, CONFIG_VERSION
+            ),
+            ConfigError::Field {
+                path,
+                field,
+                message,
+            } if field == "." => write!(f, "{}: {}", path, message),
+            ConfigError::Field {
+                path,
+                field,
+                message,
+            } => write!(f, "{}: {}: {}", path, field, message),
+            ConfigError::Write { path, message } => {
+                write!(f, "{}: couldn't write the configuration: {}", path, message)
+            }
+            ConfigError::ReadPalette { path, message } => {
+                write!(f, "{}: couldn't read the palette: {}", path, message)
+            }
+            ConfigError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// The configuration as it's written out, with the version in front of the coordinates.
+#[derive(Serialize)]
+struct Versioned<'a> {
+    version: u32,
+    #[serde(flatten)]
+    coords: &'a EaselCoords,
+}
+
+/// A palette as it's written out.
+#[derive(Serialize, Deserialize)]
+struct PaletteFile {
+    colors: Vec<PaletteEntry>,
+}
+
+/// Reads the whole of a file, turning a failure into the given kind of error.
+fn read_file(
+    path: &str,
+    error: fn(path: String, message: String) -> ConfigError,
+) -> Result<String, ConfigError> {
+    let read_error = |e: std::io::Error| error(path.to_string(), e.to_string());
+    let mut file = File::open(path).map_err(read_error)?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).map_err(read_error)?;
+    Ok(contents)
+}
+
+/// Reads a configuration in either format, bringing it up to date if it was written in an
+/// older layout.
+pub fn load(path: &str) -> Result<EaselCoords, PrinterError> {
+    let contents = read_file(path, |path, message| ConfigError::Read { path, message })?;
+    Ok(parse(&contents, ConfigFormat::from_path(path), path)?)
+}
+
+/// Parses the contents of a configuration. `path` is only used to say where any error is.
+///
+/// # Example
+///
+/// ```
+/// use passpartout_printer::config::{parse, ConfigFormat};
+///
+/// let result = parse("version = 1000", ConfigFormat::Toml, "coords.toml");
+/// assert!(result.err().unwrap().to_string().contains("version 1000"));
+/// ```
+pub fn parse(contents: &str, format: ConfigFormat, path: &str) -> Result<EaselCoords, ConfigError> {
+    let mut fields = parse_table(contents, format, path)?;
+
+    let version = match fields.remove("version") {
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| ConfigError::BadVersion {
+                path: path.to_string(),
+            })?,
+        None => 1,
+    };
+    if version > CONFIG_VERSION {
+        Err(ConfigError::TooNew {
+            path: path.to_string(),
+            version,
+        })?
+    }
+    if version < 1 {
+        Err(ConfigError::BadVersion {
+            path: path.to_string(),
+        })?
+    }
+    migrate(&mut fields, version);
+    deserialize(fields, path)
+}
+
+/// Parses the contents of a file in either format into a table of fields, so both formats can
+/// be handled the same way from there.
+fn parse_table(
+    contents: &str,
+    format: ConfigFormat,
+    path: &str,
+) -> Result<Map<String, Value>, ConfigError> {
+    let syntax = |message: String| ConfigError::Syntax {
+        path: path.to_string(),
+        message,
+    };
+    let value = match format {
+        ConfigFormat::Json => {
+            serde_json::from_str::<Value>(contents).map_err(|e| syntax(e.to_string()))?
+        }
+        ConfigFormat::Toml => {
+            let table: toml::Table = toml::from_str(contents).map_err(|e| syntax(e.to_string()))?;
+            serde_json::to_value(table).map_err(|e| syntax(e.to_string()))?
+        }
+    };
+    match value {
+        Value::Object(fields) => Ok(fields),
+        _ => Err(syntax("expected a table of settings".to_string())),
+    }
+}
+
+/// Turns a table of fields into the settings they describe, saying which field is wrong if
+/// they don't fit.
+fn deserialize<T: serde::de::DeserializeOwned>(
+    fields: Map<String, Value>,
+    path: &str,
+) -> Result<T, ConfigError> {
+    serde_path_to_error::deserialize(Value::Object(fields)).map_err(|e| ConfigError::Field {
+        path: path.to_string(),
+        field: e.path().to_string(),
+        message: e.inner().to_string(),
+    })
+}
+
+/// Brings the fields of a configuration from the given version up to `CONFIG_VERSION`, one
+/// version at a time.
+fn migrate(fields: &mut Map<String, Value>, version: u32) {
+    for from in version..CONFIG_VERSION {
+        match from {
+            1 => migrate_v1(fields),
+            _ => unreachable!("no migration from version {}", from),
+        }
+    }
+}
+
+/// Version 1 didn't always have brush widths, so configurations made before the brushes could
+/// be calibrated start out with the rough guess.
+fn migrate_v1(fields: &mut Map<String, Value>) {
+    fields
+        .entry("brush_widths")
+        .or_insert_with(|| Value::from(BRUSH_WIDTHS.to_vec()));
+}
+
+/// Writes a configuration at the current version, in the format picked by the path's
+/// extension.
+pub fn save(easel_coords: &EaselCoords, path: &str) -> Result<(), PrinterError> {
+    let versioned = Versioned {
+        version: CONFIG_VERSION,
+        coords: easel_coords,
+    };
+    write_file(&versioned, path)
+}
+
+/// Writes out anything serializable in the format picked by the path's extension.
+fn write_file<T: Serialize>(value: &T, path: &str) -> Result<(), PrinterError> {
+    let contents = match ConfigFormat::from_path(path) {
+        ConfigFormat::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+        ConfigFormat::Toml => toml::to_string(value).map_err(|e| e.to_string()),
+    };
+    let contents = contents.map_err(|message| ConfigError::Write {
+        path: path.to_string(),
+        message,
+    })?;
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = File::create(path)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+/// Reads a palette file in either format, to be used with `colors::use_palette`.
+pub fn load_palette(path: &str) -> Result<Vec<PaletteEntry>, PrinterError> {
+    let contents = read_file(path, |path, message| ConfigError::ReadPalette {
+        path,
+        message,
+    })?;
+    Ok(parse_palette(
+        &contents,
+        ConfigFormat::from_path(path),
+        path,
+    )?)
+}
+
+/// Parses the contents of a palette file, checking that it describes a palette that can be
+/// drawn with. `path` is only used to say where any error is.
+///
+/// # Example
+///
+/// ```
+/// use passpartout_printer::colors::PaletteColor;
+/// use passpartout_printer::config::{parse_palette, ConfigFormat};
+///
+/// let palette = r#"
+///     [[colors]]
+///     name = "White"
+///     rgba = [0xe0, 0xe2, 0xe5, 0xff]
+///     row = 0
+///     col = 0
+///
+///     [[colors]]
+///     name = "Black"
+///     rgba = [0x10, 0x10, 0x10, 0xff]
+///     row = 0
+///     col = 1
+/// "#;
+/// let colors = parse_palette(palette, ConfigFormat::Toml, "palette.toml").unwrap();
+/// assert_eq!(colors[1].name, PaletteColor::Black);
+///
+/// let result = parse_palette(&palette.replace("col = 1", "col = 0"), ConfigFormat::Toml, "");
+/// assert!(result.err().unwrap().to_string().contains("already taken by White"));
+/// ```
+pub fn parse_palette(
+    contents: &str,
+    format: ConfigFormat,
+    path: &str,
+) -> Result<Vec<PaletteEntry>, ConfigError> {
+    let fields = parse_table(contents, format, path)?;
+    let palette: PaletteFile = deserialize(fields, path)?;
+
+    let invalid = |field: String, message: String| ConfigError::Field {
+        path: path.to_string(),
+        field,
+        message,
+    };
+    for (index, entry) in palette.colors.iter().enumerate() {
+        let earlier = &palette.colors[..index];
+        if earlier.iter().any(|other| other.name == entry.name) {
+            Err(invalid(
+                format!("colors[{}].name", index),
+                format!("{:?} is already in the palette", entry.name),
+            ))?
+        }
+        if let Some(other) = earlier
+            .iter()
+            .find(|other| (other.row, other.col) == (entry.row, entry.col))
+        {
+            Err(invalid(
+                format!("colors[{}]", index),
+                format!(
+                    "row {}, column {} is already taken by {:?}",
+                    entry.row, entry.col, other.name
+                ),
+            ))?
+        }
+    }
+    // Borders and blank parts of the easel are painted white.
+    if !palette
+        .colors
+        .iter()
+        .any(|entry| entry.name == PaletteColor::White)
+    {
+        Err(invalid(
+            "colors".to_string(),
+            "the palette needs White for the blank canvas".to_string(),
+        ))?
+    }
+    Ok(palette.colors)
+}
+
+/// Writes a palette in the format picked by the path's extension, to be tuned and read back
+/// with `load_palette`.
+pub fn save_palette(colors: &[PaletteEntry], path: &str) -> Result<(), PrinterError> {
+    let palette = PaletteFile {
+        colors: colors.to_vec(),
+    };
+    write_file(&palette, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A configuration saved before versions or brush widths were added.
+    const V1: &str = include_str!("../tests/fixtures/coords_v1.json");
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "passpartout_printer_{}_{}",
+            std::process::id(),
+            name
+        ));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn version_1_gets_the_default_brush_widths() {
+        let coords = parse(V1, ConfigFormat::Json, "coords.json").unwrap();
+        assert_eq!(coords.brush_widths, BRUSH_WIDTHS.to_vec());
+        assert_eq!(coords.color_start.x, 800);
+    }
+
+    #[test]
+    fn version_1_keeps_calibrated_brush_widths() {
+        let calibrated = V1.replacen('{', "{\"brush_widths\": [2, 5, 9],", 1);
+        let coords = parse(&calibrated, ConfigFormat::Json, "coords.json").unwrap();
+        assert_eq!(coords.brush_widths, vec![2, 5, 9]);
+    }
+
+    #[test]
+    fn version_1_is_saved_as_the_current_version() {
+        let path = temp_path("migrated.json");
+        fs::write(&path, V1).unwrap();
+        let coords = load(&path).unwrap();
+        save(&coords, &path).unwrap();
+
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["version"], Value::from(CONFIG_VERSION));
+        assert_eq!(CONFIG_VERSION, 2);
+        assert_eq!(load(&path).unwrap(), coords);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_newer_version_is_rejected() {
+        let newer = V1.replacen('{', "{\"version\": 3,", 1);
+        let error = parse(&newer, ConfigFormat::Json, "coords.json").unwrap_err();
+        assert!(matches!(error, ConfigError::TooNew { version: 3, .. }));
+        assert_eq!(
+            error.to_string(),
+            "coords.json: the configuration is version 3 but only versions up to 2 are \
+             understood, try a newer passpartout_printer"
+        );
+    }
+
+    #[test]
+    fn a_version_that_isnt_a_whole_number_is_rejected() {
+        for version in ["0", "-1", "1.5", "\"2\""] {
+            let bad = V1.replacen('{', &format!("{{\"version\": {},", version), 1);
+            let error = parse(&bad, ConfigFormat::Json, "coords.json").unwrap_err();
+            assert!(
+                matches!(error, ConfigError::BadVersion { .. }),
+                "{}",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn saving_and_parsing_round_trips() {
+        let mut coords = parse(V1, ConfigFormat::Json, "coords.json").unwrap();
+        coords.screen_size = Some((1920, 1080));
+        coords.window = Some("PASSPARTOUT".to_string());
+        for name in ["round_trip.json", "round_trip.toml"] {
+            let path = temp_path(name);
+            save(&coords, &path).unwrap();
+            let contents = fs::read_to_string(&path).unwrap();
+            let parsed = parse(&contents, ConfigFormat::from_path(&path), &path).unwrap();
+            assert_eq!(parsed, coords, "{}", name);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    const PALETTE_JSON: &str = r#"{"colors": [
+        {"name": "White", "rgba": [224, 226, 229, 255], "row": 0, "col": 0},
+        {"name": "Red", "rgba": [234, 32, 37, 255], "row": 0, "col": 1}
+    ]}"#;
+
+    const PALETTE_TOML: &str = r#"
+        [[colors]]
+        name = "White"
+        rgba = [224, 226, 229, 255]
+        row = 0
+        col = 0
+
+        [[colors]]
+        name = "Red"
+        rgba = [234, 32, 37, 255]
+        row = 0
+        col = 1
+    "#;
+
+    #[test]
+    fn palettes_parse_from_json_and_toml() {
+        let json = parse_palette(PALETTE_JSON, ConfigFormat::Json, "palette.json").unwrap();
+        let toml = parse_palette(PALETTE_TOML, ConfigFormat::Toml, "palette.toml").unwrap();
+        assert_eq!(json, toml);
+        assert_eq!(
+            json[1],
+            PaletteEntry {
+                name: PaletteColor::Red,
+                rgba: [234, 32, 37, 255],
+                row: 0,
+                col: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn a_palette_without_white_is_rejected() {
+        let without_white = PALETTE_TOML.replacen("White", "Black", 1);
+        let error = parse_palette(&without_white, ConfigFormat::Toml, "palette.toml").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "palette.toml: colors: the palette needs White for the blank canvas"
+        );
+    }
+
+    #[test]
+    fn a_palette_color_can_only_be_listed_once() {
+        let twice = PALETTE_JSON.replace("Red", "White");
+        let error = parse_palette(&twice, ConfigFormat::Json, "palette.json").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "palette.json: colors[1].name: White is already in the palette"
+        );
+    }
+
+    #[test]
+    fn a_palette_can_only_name_the_games_colors() {
+        let unknown = PALETTE_JSON.replace("Red", "Teal");
+        let error = parse_palette(&unknown, ConfigFormat::Json, "palette.json").unwrap_err();
+        let message = error.to_string();
+        assert!(
+            message.starts_with("palette.json: colors[1].name: "),
+            "{}",
+            message
+        );
+        assert!(message.contains("unknown variant `Teal`"), "{}", message);
+    }
+
+    #[test]
+    fn saving_and_parsing_a_palette_round_trips() {
+        let colors = parse_palette(PALETTE_JSON, ConfigFormat::Json, "palette.json").unwrap();
+        for name in ["palette.json", "palette.toml"] {
+            let path = temp_path(name);
+            save_palette(&colors, &path).unwrap();
+            assert_eq!(load_palette(&path).unwrap(), colors, "{}", name);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn a_missing_field_is_named() {
+        let missing = V1.replace("\"pen\": {\"x\": 10, \"y\": 50},", "");
+        let error = parse(&missing, ConfigFormat::Json, "coords.json").unwrap_err();
+        assert!(
+            error.to_string().contains("missing field `pen`"),
+            "{}",
+            error
+        );
+    }
+}
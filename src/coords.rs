@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::convert::From;
 use std::ops::{Add, Mul, Sub};
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 pub struct Coord {
     pub x: i32,
     pub y: i32,
@@ -28,7 +28,7 @@ impl From<(i32, i32)> for Coord {
     }
 }
 
-impl<'a, 'b> Add<&'a Coord> for &'b Coord {
+impl<'a> Add<&'a Coord> for &Coord {
     type Output = Coord;
     fn add(self, rhs: &'a Coord) -> Self::Output {
         Coord {
@@ -48,7 +48,7 @@ impl<'a> Add<&'a Coord> for Coord {
     }
 }
 
-impl<'a, 'b> Sub<&'a Coord> for &'b Coord {
+impl<'a> Sub<&'a Coord> for &Coord {
     type Output = Coord;
     fn sub(self, rhs: &'a Coord) -> Self::Output {
         Coord {
@@ -68,7 +68,7 @@ impl<'a> Sub<&'a Coord> for Coord {
     }
 }
 
-impl<'a> Mul<i32> for &'a Coord {
+impl Mul<i32> for &Coord {
     type Output = Coord;
     fn mul(self, rhs: i32) -> Self::Output {
         Coord {
@@ -77,3 +77,203 @@ impl<'a> Mul<i32> for &'a Coord {
         }
     }
 }
+
+// Which sides of a rectangle a point lies beyond, as used by Cohen-Sutherland clipping.
+const INSIDE: u8 = 0;
+const LEFT: u8 = 1;
+const RIGHT: u8 = 2;
+const ABOVE: u8 = 4;
+const BELOW: u8 = 8;
+
+fn outcode(point: (f32, f32), ul: Coord, lr: Coord) -> u8 {
+    let mut code = INSIDE;
+    if point.0 < ul.x as f32 {
+        code |= LEFT;
+    } else if point.0 > lr.x as f32 {
+        code |= RIGHT;
+    }
+    if point.1 < ul.y as f32 {
+        code |= ABOVE;
+    } else if point.1 > lr.y as f32 {
+        code |= BELOW;
+    }
+    code
+}
+
+/// Clips the line from `start` to `end` to the rectangle between `ul` and `lr`, inclusive,
+/// with the Cohen-Sutherland algorithm. Returns `None` if none of the line is in the rectangle.
+///
+/// # Example
+///
+/// ```
+/// use passpartout_printer::coords::{clip_line, Coord};
+///
+/// let (ul, lr) = (Coord::new(0, 0), Coord::new(100, 100));
+/// assert_eq!(
+///     clip_line(Coord::new(-50, 50), Coord::new(50, 50), ul, lr),
+///     Some((Coord::new(0, 50), Coord::new(50, 50))),
+/// );
+/// assert_eq!(clip_line(Coord::new(-50, 50), Coord::new(-10, 50), ul, lr), None);
+/// ```
+pub fn clip_line(start: Coord, end: Coord, ul: Coord, lr: Coord) -> Option<(Coord, Coord)> {
+    let (mut x0, mut y0) = (start.x as f32, start.y as f32);
+    let (mut x1, mut y1) = (end.x as f32, end.y as f32);
+    let (left, right) = (ul.x as f32, lr.x as f32);
+    let (top, bottom) = (ul.y as f32, lr.y as f32);
+    let mut code0 = outcode((x0, y0), ul, lr);
+    let mut code1 = outcode((x1, y1), ul, lr);
+
+    loop {
+        if code0 | code1 == INSIDE {
+            break;
+        }
+        if code0 & code1 != INSIDE {
+            return None;
+        }
+
+        // Move whichever end is outside onto the edge of the rectangle it lies beyond.
+        let code = if code0 != INSIDE { code0 } else { code1 };
+        let (x, y) = if code & BELOW != 0 {
+            (x0 + (x1 - x0) * (bottom - y0) / (y1 - y0), bottom)
+        } else if code & ABOVE != 0 {
+            (x0 + (x1 - x0) * (top - y0) / (y1 - y0), top)
+        } else if code & RIGHT != 0 {
+            (right, y0 + (y1 - y0) * (right - x0) / (x1 - x0))
+        } else {
+            (left, y0 + (y1 - y0) * (left - x0) / (x1 - x0))
+        };
+        if code == code0 {
+            x0 = x;
+            y0 = y;
+            code0 = outcode((x0, y0), ul, lr);
+        } else {
+            x1 = x;
+            y1 = y;
+            code1 = outcode((x1, y1), ul, lr);
+        }
+    }
+
+    // Rounding can nudge a point that was moved onto an edge just past it.
+    let round = |x: f32, y: f32| {
+        Coord::new(
+            (x.round() as i32).clamp(ul.x, lr.x),
+            (y.round() as i32).clamp(ul.y, lr.y),
+        )
+    };
+    Some((round(x0, y0), round(x1, y1)))
+}
+
+/// Clips a stroke through the given points to the rectangle between `ul` and `lr`. A stroke
+/// that leaves the rectangle and comes back in is split into separate strokes, one for each
+/// part inside.
+///
+/// # Example
+///
+/// ```
+/// use passpartout_printer::coords::{clip_stroke, Coord};
+///
+/// let points = Coord::from_slice(&[(10, 10), (10, 200), (50, 10)]);
+/// let strokes = clip_stroke(&points, Coord::new(0, 0), Coord::new(100, 100));
+/// assert_eq!(strokes.len(), 2);
+/// ```
+pub fn clip_stroke(points: &[Coord], ul: Coord, lr: Coord) -> Vec<Vec<Coord>> {
+    if let [point] = points {
+        return match outcode((point.x as f32, point.y as f32), ul, lr) {
+            INSIDE => vec![vec![*point]],
+            _ => vec![],
+        };
+    }
+
+    let mut strokes: Vec<Vec<Coord>> = vec![];
+    let mut current: Vec<Coord> = vec![];
+    for segment in points.windows(2) {
+        match clip_line(segment[0], segment[1], ul, lr) {
+            Some((start, end)) => {
+                if current.last() != Some(&start) {
+                    if !current.is_empty() {
+                        strokes.push(current);
+                    }
+                    current = vec![start];
+                }
+                current.push(end);
+            }
+            None => {
+                if !current.is_empty() {
+                    strokes.push(current);
+                    current = vec![];
+                }
+            }
+        }
+    }
+    if !current.is_empty() {
+        strokes.push(current);
+    }
+    strokes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UL: Coord = Coord { x: 0, y: 0 };
+    const LR: Coord = Coord { x: 100, y: 100 };
+
+    #[test]
+    fn a_line_outside_is_dropped() {
+        assert_eq!(
+            clip_line(Coord::new(-10, -10), Coord::new(-5, 200), UL, LR),
+            None
+        );
+        // Each end is beyond a different edge, but the line still misses the corner.
+        assert_eq!(
+            clip_line(Coord::new(-30, 10), Coord::new(10, -30), UL, LR),
+            None
+        );
+        assert_eq!(
+            clip_line(Coord::new(101, 0), Coord::new(101, 100), UL, LR),
+            None
+        );
+    }
+
+    #[test]
+    fn a_line_crossing_two_edges_is_cut_at_both() {
+        assert_eq!(
+            clip_line(Coord::new(-20, 40), Coord::new(40, -20), UL, LR),
+            Some((Coord::new(0, 20), Coord::new(20, 0)))
+        );
+        assert_eq!(
+            clip_line(Coord::new(-50, 50), Coord::new(150, 50), UL, LR),
+            Some((Coord::new(0, 50), Coord::new(100, 50)))
+        );
+    }
+
+    #[test]
+    fn lines_along_the_edges_are_kept() {
+        assert_eq!(
+            clip_line(Coord::new(0, -10), Coord::new(0, 110), UL, LR),
+            Some((Coord::new(0, 0), Coord::new(0, 100)))
+        );
+        assert_eq!(
+            clip_line(Coord::new(-10, 100), Coord::new(110, 100), UL, LR),
+            Some((Coord::new(0, 100), Coord::new(100, 100)))
+        );
+    }
+
+    #[test]
+    fn a_stroke_that_comes_back_is_split() {
+        let points = Coord::from_slice(&[(10, 10), (10, 200), (50, 10)]);
+        assert_eq!(
+            clip_stroke(&points, UL, LR),
+            vec![
+                Coord::from_slice(&[(10, 10), (10, 100)]),
+                Coord::from_slice(&[(31, 100), (50, 10)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_stroke_inside_is_untouched() {
+        let points = Coord::from_slice(&[(10, 10), (90, 10), (90, 90)]);
+        assert_eq!(clip_stroke(&points, UL, LR), vec![points.clone()]);
+    }
+}
@@ -0,0 +1,580 @@
+use crate::colors::Palette;
+use image::imageops::ColorMap;
+use image::{Rgba, RgbaImage};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// The ways of dithering an image down to the palette.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DitherAlgorithm {
+    /// Floyd-Steinberg error diffusion, spreading the error over four neighbours.
+    FloydSteinberg,
+
+    /// Atkinson error diffusion, which only spreads three quarters of the error and so keeps
+    /// more contrast.
+    Atkinson,
+
+    /// Jarvis-Judice-Ninke error diffusion, spreading the error over twelve neighbours for a
+    /// smoother result.
+    JarvisJudiceNinke,
+
+    /// Sierra error diffusion, a cheaper take on Jarvis-Judice-Ninke.
+    Sierra,
+
+    /// Ordered dithering with an 8x8 Bayer matrix. Each pixel is dithered on its own, so the
+    /// result has a regular pattern and fewer stray pixels than error diffusion.
+    Bayer,
+
+    /// Ordered dithering with a blue noise texture, which hides the pattern of `Bayer`.
+    BlueNoise,
+}
+
+impl FromStr for DitherAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DitherAlgorithm, String> {
+        match s {
+            "floyd-steinberg" => Ok(DitherAlgorithm::FloydSteinberg),
+            "atkinson" => Ok(DitherAlgorithm::Atkinson),
+            "jarvis" => Ok(DitherAlgorithm::JarvisJudiceNinke),
+            "sierra" => Ok(DitherAlgorithm::Sierra),
+            "bayer" => Ok(DitherAlgorithm::Bayer),
+            "blue-noise" => Ok(DitherAlgorithm::BlueNoise),
+            _ => Err(format!("unknown dithering algorithm '{}'", s)),
+        }
+    }
+}
+
+/// Options controlling how an image is dithered.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DitherOptions {
+    /// The dithering algorithm to use.
+    pub algorithm: DitherAlgorithm,
+
+    /// Whether error diffusion runs along every other row from right to left, which breaks up
+    /// the diagonal streaks diffusion can leave. Ordered dithering ignores this.
+    pub serpentine: bool,
+
+    /// The shortest run of one color to leave along a row, apart from the last run of each
+    /// row. Each pixel keeps the color of the run it's in until the run is this long, so every
+    /// run costs fewer strokes and color changes at the price of a noisier image. With error
+    /// diffusion the error of holding a color is spread on like any other. 0 and 1 leave the
+    /// runs as they fall.
+    pub min_run: u32,
+}
+
+impl Default for DitherOptions {
+    fn default() -> DitherOptions {
+        DitherOptions {
+            algorithm: DitherAlgorithm::FloydSteinberg,
+            serpentine: false,
+            min_run: 1,
+        }
+    }
+}
+
+/// How far ordered dithering nudges each channel, roughly the distance between neighbouring
+/// colors of the palette.
+const ORDERED_SPREAD: f32 = 64.0;
+
+/// Dithers an image to the colors of the palette, leaving every pixel one of the palette's
+/// colors.
+///
+/// # Example
+///
+/// ```
+/// use image::{Rgba, RgbaImage};
+/// use passpartout_printer::colors::{Palette, PaletteColor};
+/// use passpartout_printer::dither::{dither, DitherAlgorithm, DitherOptions};
+///
+/// let mut image = RgbaImage::from_pixel(16, 16, image::Rgba([0x40, 0x40, 0x40, 0xff]));
+/// let options = DitherOptions {
+///     algorithm: DitherAlgorithm::Bayer,
+///     ..DitherOptions::default()
+/// };
+/// dither(&mut image, &Palette::new(), &options);
+///
+/// let palette = Palette::new();
+/// let colors: Vec<_> = palette.colormap.iter().map(PaletteColor::get_rgba).collect();
+/// assert!(image.pixels().all(|p| colors.contains(p)));
+/// ```
+///
+/// Asking for a minimum run length keeps the rows from breaking up into single pixels:
+///
+/// ```
+/// use image::RgbaImage;
+/// use passpartout_printer::colors::Palette;
+/// use passpartout_printer::dither::{dither, DitherOptions};
+///
+/// let gradient = |x: u32, _| image::Rgba([(x * 4) as u8, 0x80, 0x40, 0xff]);
+/// let mut image = RgbaImage::from_fn(64, 8, gradient);
+/// let options = DitherOptions {
+///     min_run: 4,
+///     ..DitherOptions::default()
+/// };
+/// dither(&mut image, &Palette::new(), &options);
+///
+/// for y in 0..8 {
+///     let row: Vec<_> = (0..64).map(|x| *image.get_pixel(x, y)).collect();
+///     let mut runs = vec![1];
+///     for pair in row.windows(2) {
+///         if pair[0] == pair[1] {
+///             *runs.last_mut().unwrap() += 1;
+///         } else {
+///             runs.push(1);
+///         }
+///     }
+///     runs.pop();
+///     assert!(runs.iter().all(|&length| length >= 4));
+/// }
+/// ```
+pub fn dither(image: &mut RgbaImage, palette: &Palette, options: &DitherOptions) {
+    match options.algorithm {
+        DitherAlgorithm::FloydSteinberg => diffuse(image, palette, FLOYD_STEINBERG, 16.0, options),
+        DitherAlgorithm::Atkinson => diffuse(image, palette, ATKINSON, 8.0, options),
+        DitherAlgorithm::JarvisJudiceNinke => {
+            diffuse(image, palette, JARVIS_JUDICE_NINKE, 48.0, options)
+        }
+        DitherAlgorithm::Sierra => diffuse(image, palette, SIERRA, 32.0, options),
+        DitherAlgorithm::Bayer => ordered(image, palette, &bayer_matrix(), BAYER_SIZE, options),
+        DitherAlgorithm::BlueNoise => {
+            ordered(image, palette, blue_noise(), BLUE_NOISE_SIZE, options)
+        }
+    }
+}
+
+// Error diffusion kernels, as the offset of each neighbour from the current pixel and its share
+// of the error. The shares are divided by the divisor given alongside the kernel.
+const FLOYD_STEINBERG: &[(i32, i32, f32)] = &[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)];
+
+const ATKINSON: &[(i32, i32, f32)] = &[
+    (1, 0, 1.0),
+    (2, 0, 1.0),
+    (-1, 1, 1.0),
+    (0, 1, 1.0),
+    (1, 1, 1.0),
+    (0, 2, 1.0),
+];
+
+const JARVIS_JUDICE_NINKE: &[(i32, i32, f32)] = &[
+    (1, 0, 7.0),
+    (2, 0, 5.0),
+    (-2, 1, 3.0),
+    (-1, 1, 5.0),
+    (0, 1, 7.0),
+    (1, 1, 5.0),
+    (2, 1, 3.0),
+    (-2, 2, 1.0),
+    (-1, 2, 3.0),
+    (0, 2, 5.0),
+    (1, 2, 3.0),
+    (2, 2, 1.0),
+];
+
+const SIERRA: &[(i32, i32, f32)] = &[
+    (1, 0, 5.0),
+    (2, 0, 3.0),
+    (-2, 1, 2.0),
+    (-1, 1, 4.0),
+    (0, 1, 5.0),
+    (1, 1, 4.0),
+    (2, 1, 2.0),
+    (-1, 2, 2.0),
+    (0, 2, 3.0),
+    (1, 2, 2.0),
+];
+
+/// Keeps track of the run of one color along the row being dithered, holding pixels to the
+/// run's color until it's at least `min_run` long.
+struct Runs {
+    min_run: u32,
+    color: Option<Rgba<u8>>,
+    length: u32,
+}
+
+impl Runs {
+    fn new(min_run: u32) -> Runs {
+        Runs {
+            min_run,
+            color: None,
+            length: 0,
+        }
+    }
+
+    /// Starts over at the beginning of a row.
+    fn new_row(&mut self) {
+        self.color = None;
+        self.length = 0;
+    }
+
+    /// Takes the next pixel of the row, already set to its closest palette color. The pixel is
+    /// set back to the color of the current run if the run is still too short to end. If it
+    /// ends the run, the next run starts with the color given by `start`, which should suit
+    /// the pixels the new run will have to cover.
+    fn hold(&mut self, pixel: &mut Rgba<u8>, start: impl FnOnce() -> Rgba<u8>) {
+        match self.color {
+            Some(color) if *pixel == color => self.length += 1,
+            Some(color) if self.length < self.min_run => {
+                *pixel = color;
+                self.length += 1;
+            }
+            _ => {
+                *pixel = start();
+                if self.color == Some(*pixel) {
+                    self.length += 1;
+                } else {
+                    self.color = Some(*pixel);
+                    self.length = 1;
+                }
+            }
+        }
+    }
+}
+
+/// The palette color closest to the average of the given colors. Picking the color of a run
+/// this way keeps holding it from piling up error that would swing the next run the other way.
+fn closest_to_average(palette: &Palette, colors: impl Iterator<Item = [f32; 3]>) -> Rgba<u8> {
+    let (mut sum, mut count) = ([0.0; 3], 0.0);
+    for color in colors {
+        for c in 0..3 {
+            sum[c] += color[c];
+        }
+        count += 1.0;
+    }
+    let mut average = Rgba([0, 0, 0, 0xff]);
+    for c in 0..3 {
+        average[c] = (sum[c] / count).round().clamp(0.0, 255.0) as u8;
+    }
+    palette.map_color(&mut average);
+    average
+}
+
+/// Dithers by error diffusion: each pixel is set to its closest palette color and the
+/// difference is spread over the neighbours that haven't been visited yet.
+fn diffuse(
+    image: &mut RgbaImage,
+    palette: &Palette,
+    kernel: &[(i32, i32, f32)],
+    divisor: f32,
+    options: &DitherOptions,
+) {
+    let (width, height) = (image.width() as i32, image.height() as i32);
+    let mut colors: Vec<[f32; 3]> = image
+        .pixels()
+        .map(|p| [f32::from(p[0]), f32::from(p[1]), f32::from(p[2])])
+        .collect();
+    let mut runs = Runs::new(options.min_run);
+    let run_length = options.min_run.max(1) as i32;
+
+    for y in 0..height {
+        // Running backwards, the kernel is mirrored so the error still goes ahead of the scan.
+        let backwards = options.serpentine && y % 2 == 1;
+        runs.new_row();
+        for i in 0..width {
+            let x = if backwards { width - 1 - i } else { i };
+            let pixel = image.get_pixel_mut(x as u32, y as u32);
+            let old = colors[(y * width + x) as usize];
+            for c in 0..3 {
+                pixel[c] = old[c].round().clamp(0.0, 255.0) as u8;
+            }
+            palette.map_color(pixel);
+            runs.hold(pixel, || {
+                let ahead = (i..(i + run_length).min(width)).map(|i| {
+                    let x = if backwards { width - 1 - i } else { i };
+                    colors[(y * width + x) as usize]
+                });
+                closest_to_average(palette, ahead)
+            });
+
+            let error = [
+                old[0] - f32::from(pixel[0]),
+                old[1] - f32::from(pixel[1]),
+                old[2] - f32::from(pixel[2]),
+            ];
+            for (dx, dy, weight) in kernel.iter() {
+                let nx = if backwards { x - dx } else { x + dx };
+                let ny = y + dy;
+                if nx < 0 || nx >= width || ny >= height {
+                    continue;
+                }
+                let neighbour = &mut colors[(ny * width + nx) as usize];
+                for c in 0..3 {
+                    neighbour[c] += error[c] * weight / divisor;
+                }
+            }
+        }
+    }
+}
+
+/// Dithers with a threshold map: each pixel is nudged by the map's value at its position,
+/// tiled over the image, and then set to its closest palette color.
+fn ordered(
+    image: &mut RgbaImage,
+    palette: &Palette,
+    map: &[f32],
+    size: usize,
+    options: &DitherOptions,
+) {
+    let mut runs = Runs::new(options.min_run);
+    let run_length = options.min_run.max(1) as usize;
+    let width = image.width() as usize;
+
+    for y in 0..image.height() {
+        let nudged: Vec<[f32; 3]> = (0..width)
+            .map(|x| {
+                let pixel = image.get_pixel(x as u32, y);
+                let threshold = map[(y as usize % size) * size + x % size];
+                let mut nudged = [0.0; 3];
+                for c in 0..3 {
+                    nudged[c] = f32::from(pixel[c]) + threshold * ORDERED_SPREAD;
+                }
+                nudged
+            })
+            .collect();
+
+        runs.new_row();
+        for x in 0..width {
+            let pixel = image.get_pixel_mut(x as u32, y);
+            for c in 0..3 {
+                pixel[c] = nudged[x][c].round().clamp(0.0, 255.0) as u8;
+            }
+            palette.map_color(pixel);
+            runs.hold(pixel, || {
+                let ahead = nudged[x..(x + run_length).min(width)].iter().copied();
+                closest_to_average(palette, ahead)
+            });
+        }
+    }
+}
+
+/// The width and height of the Bayer matrix.
+const BAYER_SIZE: usize = 8;
+
+/// Builds the 8x8 Bayer matrix as thresholds from -0.5 to 0.5.
+fn bayer_matrix() -> Vec<f32> {
+    let n = BAYER_SIZE * BAYER_SIZE;
+    (0..n)
+        .map(|i| {
+            let (x, y) = (i % BAYER_SIZE, i / BAYER_SIZE);
+            // Interleave the bits of x ^ y and y, most significant first, to get the rank.
+            let xc = x ^ y;
+            let mut rank = 0;
+            for bit in (0..3).rev() {
+                rank = (rank << 2) | (((xc >> bit) & 1) << 1) | ((y >> bit) & 1);
+            }
+            (rank as f32 + 0.5) / n as f32 - 0.5
+        })
+        .collect()
+}
+
+/// The width and height of the blue noise texture.
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// A blue noise texture as thresholds from -0.5 to 0.5, made the first time it's needed.
+fn blue_noise() -> &'static [f32] {
+    static TEXTURE: OnceLock<Vec<f32>> = OnceLock::new();
+    TEXTURE.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE, 1.5))
+}
+
+/// Makes a tileable blue noise texture with Ulichney's void-and-cluster method. Points are
+/// ranked by removing them from the tightest clusters of a starting pattern, then adding
+/// them in the largest voids until every pixel has a rank.
+fn void_and_cluster(size: usize, sigma: f32) -> Vec<f32> {
+    let n = size * size;
+
+    // How much each point adds to the "energy" of every other point, wrapping around the edges
+    // so the texture tiles.
+    let falloff: Vec<f32> = (0..n)
+        .map(|i| {
+            let wrap = |d: usize| d.min(size - d) as f32;
+            let (dx, dy) = (wrap(i % size), wrap(i / size));
+            (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let update = |energy: &mut [f32], point: usize, sign: f32| {
+        let (px, py) = (point % size, point / size);
+        for (i, e) in energy.iter_mut().enumerate() {
+            let dx = (i % size + size - px) % size;
+            let dy = (i / size + size - py) % size;
+            *e += sign * falloff[dy * size + dx];
+        }
+    };
+    let tightest_cluster = |pattern: &[bool], energy: &[f32]| {
+        (0..n)
+            .filter(|&i| pattern[i])
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+    };
+    let largest_void = |pattern: &[bool], energy: &[f32]| {
+        (0..n)
+            .filter(|&i| !pattern[i])
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+    };
+
+    // Start from a sprinkling of points picked by a fixed pseudo-random sequence, so the
+    // texture is the same every run.
+    let mut pattern = vec![false; n];
+    let mut seed: u32 = 0x9e37_79b9;
+    for _ in 0..n / 10 {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        pattern[seed as usize % n] = true;
+    }
+    let mut energy = vec![0.0; n];
+    for point in (0..n).filter(|&i| pattern[i]) {
+        update(&mut energy, point, 1.0);
+    }
+
+    // Spread the starting points out evenly by moving the most crowded point into the
+    // emptiest spot until that would put it straight back.
+    for _ in 0..n {
+        let cluster = match tightest_cluster(&pattern, &energy) {
+            Some(cluster) => cluster,
+            None => break,
+        };
+        pattern[cluster] = false;
+        update(&mut energy, cluster, -1.0);
+        let void = largest_void(&pattern, &energy).unwrap_or(cluster);
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0; n];
+    let ones = pattern.iter().filter(|&&p| p).count();
+
+    // Rank the starting points from the most crowded down.
+    let mut removing = pattern.clone();
+    let mut removing_energy = energy.clone();
+    for r in (0..ones).rev() {
+        if let Some(cluster) = tightest_cluster(&removing, &removing_energy) {
+            removing[cluster] = false;
+            update(&mut removing_energy, cluster, -1.0);
+            rank[cluster] = r;
+        }
+    }
+
+    // Rank everything else by filling in the emptiest spot each time.
+    for r in ones..n {
+        if let Some(void) = largest_void(&pattern, &energy) {
+            pattern[void] = true;
+            update(&mut energy, void, 1.0);
+            rank[void] = r;
+        }
+    }
+
+    rank.iter()
+        .map(|&r| (r as f32 + 0.5) / n as f32 - 0.5)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALGORITHMS: [DitherAlgorithm; 6] = [
+        DitherAlgorithm::FloydSteinberg,
+        DitherAlgorithm::Atkinson,
+        DitherAlgorithm::JarvisJudiceNinke,
+        DitherAlgorithm::Sierra,
+        DitherAlgorithm::Bayer,
+        DitherAlgorithm::BlueNoise,
+    ];
+
+    /// A gradient with noise on top, which dithers into plenty of short runs.
+    fn noisy_gradient() -> RgbaImage {
+        let mut seed: u32 = 12345;
+        RgbaImage::from_fn(96, 24, |x, y| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let noise = (seed >> 16) % 64;
+            Rgba([
+                (x * 2 + noise) as u8,
+                (y * 8 + noise) as u8,
+                (255 - x * 2) as u8,
+                0xff,
+            ])
+        })
+    }
+
+    /// The lengths of the runs of one color along a row, in the order the row was dithered.
+    fn runs(image: &RgbaImage, y: u32, backwards: bool) -> Vec<usize> {
+        let mut row: Vec<Rgba<u8>> = (0..image.width()).map(|x| *image.get_pixel(x, y)).collect();
+        if backwards {
+            row.reverse();
+        }
+        let mut runs = vec![];
+        let mut last = None;
+        for pixel in row {
+            if last == Some(pixel) {
+                *runs.last_mut().unwrap() += 1;
+            } else {
+                runs.push(1);
+                last = Some(pixel);
+            }
+        }
+        runs
+    }
+
+    #[test]
+    fn min_run_only_leaves_short_runs_at_the_end_of_a_row() {
+        for algorithm in ALGORITHMS {
+            for serpentine in [false, true] {
+                for min_run in [2, 4, 7] {
+                    let mut image = noisy_gradient();
+                    let options = DitherOptions {
+                        algorithm,
+                        serpentine,
+                        min_run,
+                    };
+                    dither(&mut image, &Palette::new(), &options);
+
+                    // Ordered dithering always runs left to right.
+                    let diffused = !matches!(
+                        algorithm,
+                        DitherAlgorithm::Bayer | DitherAlgorithm::BlueNoise
+                    );
+                    for y in 0..image.height() {
+                        let backwards = diffused && serpentine && y % 2 == 1;
+                        let mut runs = runs(&image, y, backwards);
+                        runs.pop();
+                        assert!(
+                            runs.iter().all(|&length| length >= min_run as usize),
+                            "{:?}, serpentine {}, min run {}, row {}: {:?}",
+                            algorithm,
+                            serpentine,
+                            min_run,
+                            y,
+                            runs
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn min_run_of_0_or_1_leaves_runs_as_they_fall() {
+        for algorithm in ALGORITHMS {
+            let dithered = |min_run| {
+                let mut image = noisy_gradient();
+                let options = DitherOptions {
+                    algorithm,
+                    min_run,
+                    ..DitherOptions::default()
+                };
+                dither(&mut image, &Palette::new(), &options);
+                image
+            };
+            let unheld = dithered(0);
+            assert_eq!(unheld, dithered(1), "{:?}", algorithm);
+
+            // Without holding, the noise breaks rows up into single pixels.
+            let shortest = (0..unheld.height())
+                .flat_map(|y| runs(&unheld, y, false))
+                .min();
+            assert_eq!(shortest, Some(1), "{:?}", algorithm);
+        }
+    }
+}
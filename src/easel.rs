@@ -1,16 +1,18 @@
-use crate::colors::PaletteColor;
-use crate::coords::Coord;
-use enigo::{Enigo, MouseButton, MouseControllable};
+use crate::backend::PointerBackend;
+use crate::colors::{palette_in_use, PaletteColor};
+use crate::config;
+use crate::coords::{clip_stroke, Coord};
+use crate::error::PrinterError;
+use crate::plan::{plan_shape, DrawOp, DrawPlan};
+use crate::window::WindowProvider;
+use enigo::Enigo;
 use serde::{Deserialize, Serialize};
-use std::error::Error;
 use std::f32;
-use std::fs::File;
-use std::i32;
-use std::io::{Read, Write};
-use std::thread;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 /// A structure containing the location of all the tools on the easel.
 pub struct EaselCoords {
     pub portrait_bounds: (Coord, Coord),
@@ -24,26 +26,315 @@ pub struct EaselCoords {
     pub color_start: Coord,
     pub color_row_step: i32,
     pub color_col_step: i32,
+
+    /// How many pixels wide the brush paints at each brush size, from 0 to `NUM_BRUSH_STEPS`.
+    pub brush_widths: Vec<u32>,
+
+    /// The resolution of the screen the coordinates were taken at, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub screen_size: Option<(u32, u32)>,
+
+    /// The title of the game window the coordinates are relative to. Without one, the
+    /// coordinates are positions on the screen.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<String>,
 }
 
 impl EaselCoords {
-    pub fn new(path: String) -> Result<EaselCoords, Box<dyn Error>> {
-        let mut file = File::open(path)?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
-        let win: EaselCoords = serde_json::from_str(&contents)?;
-        Ok(win)
-    }
-
-    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
-        let j = serde_json::to_string_pretty(self)?;
-        let mut file = File::create(&path)?;
-        file.write_all(j.as_bytes())?;
-        Ok(())
+    /// Loads the coordinates from a JSON or TOML configuration file, migrating older layouts.
+    pub fn new(path: String) -> Result<EaselCoords, PrinterError> {
+        config::load(&path)
+    }
+
+    /// Saves the coordinates as JSON, or as TOML if the path ends in ".toml".
+    pub fn save(&self, path: &str) -> Result<(), PrinterError> {
+        config::save(self, path)
+    }
+
+    /// Scales every coordinate from the screen resolution `from` to the resolution `to`, for
+    /// reusing a configuration after the game's resolution has changed. Assumes the game's
+    /// interface grows and shrinks with the resolution.
+    ///
+    /// Coordinates relative to a game window are scaled the same way, which only holds if the
+    /// window fills the screen at both resolutions.
+    pub fn scaled(&self, from: (u32, u32), to: (u32, u32)) -> EaselCoords {
+        let scale_x = to.0 as f32 / from.0 as f32;
+        let scale_y = to.1 as f32 / from.1 as f32;
+        let scale = |coord: Coord| {
+            Coord::new(
+                (coord.x as f32 * scale_x).round() as i32,
+                (coord.y as f32 * scale_y).round() as i32,
+            )
+        };
+        // The brush is round, so it can't stretch along just one axis.
+        let brush_scale = (scale_x * scale_y).sqrt();
+
+        EaselCoords {
+            portrait_bounds: (scale(self.portrait_bounds.0), scale(self.portrait_bounds.1)),
+            landscape_bounds: (
+                scale(self.landscape_bounds.0),
+                scale(self.landscape_bounds.1),
+            ),
+            paintbrush: scale(self.paintbrush),
+            spray_can: scale(self.spray_can),
+            pen: scale(self.pen),
+            decrease_brush: scale(self.decrease_brush),
+            increase_brush: scale(self.increase_brush),
+            change_orientation: scale(self.change_orientation),
+            color_start: scale(self.color_start),
+            color_row_step: (self.color_row_step as f32 * scale_x).round() as i32,
+            color_col_step: (self.color_col_step as f32 * scale_y).round() as i32,
+            brush_widths: self
+                .brush_widths
+                .iter()
+                .map(|width| ((*width as f32 * brush_scale).round() as u32).max(1))
+                .collect(),
+            screen_size: Some(to),
+            window: self.window.clone(),
+        }
+    }
+
+    /// Moves every coordinate by `offset`. Subtracting the window's origin turns screen
+    /// coordinates into ones relative to the window, and adding it turns them back.
+    pub fn translated(&self, offset: Coord) -> EaselCoords {
+        let translate = |coord: Coord| coord + &offset;
+        EaselCoords {
+            portrait_bounds: (
+                translate(self.portrait_bounds.0),
+                translate(self.portrait_bounds.1),
+            ),
+            landscape_bounds: (
+                translate(self.landscape_bounds.0),
+                translate(self.landscape_bounds.1),
+            ),
+            paintbrush: translate(self.paintbrush),
+            spray_can: translate(self.spray_can),
+            pen: translate(self.pen),
+            decrease_brush: translate(self.decrease_brush),
+            increase_brush: translate(self.increase_brush),
+            change_orientation: translate(self.change_orientation),
+            color_start: translate(self.color_start),
+            ..self.clone()
+        }
+    }
+
+    /// Makes the coordinates, taken as positions on the screen, relative to the window with the
+    /// given title whose upper-left corner is at `origin`.
+    pub fn relative_to(&self, title: &str, origin: Coord) -> EaselCoords {
+        EaselCoords {
+            window: Some(title.to_string()),
+            ..self.translated(&origin * -1)
+        }
+    }
+
+    /// Returns the bounds of the easel for the given orientation, relative to the game window
+    /// if the configuration names one.
+    pub fn bounds(&self, orientation: Orientation) -> (Coord, Coord) {
+        match orientation {
+            Orientation::Portrait => self.portrait_bounds,
+            Orientation::Landscape => self.landscape_bounds,
+        }
     }
+
+    /// Returns how many pixels wide the brush paints at the given brush size. Sizes missing
+    /// from `brush_widths` fall back to a rough guess.
+    pub fn brush_width(&self, brush_size: i32) -> u32 {
+        let brush_size = brush_size.clamp(0, NUM_BRUSH_STEPS) as usize;
+        self.brush_widths
+            .get(brush_size)
+            .copied()
+            .unwrap_or(BRUSH_WIDTHS[brush_size])
+    }
+
+    /// Looks for mistakes in the configuration that would otherwise only show up partway
+    /// through a drawing, returning a description of each one found.
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = vec![];
+        let orientations = [
+            ("portrait", Orientation::Portrait),
+            ("landscape", Orientation::Landscape),
+        ];
+
+        for (name, orientation) in orientations.iter() {
+            let (ul, lr) = self.bounds(*orientation);
+            if ul.x < 0 || ul.y < 0 {
+                problems.push(format!(
+                    "the upper left corner of the {} easel is off the screen at {:?}",
+                    name, ul
+                ));
+            }
+            if ul.x >= lr.x || ul.y >= lr.y {
+                problems.push(format!(
+                    "the upper left corner of the {} easel, {:?}, isn't above and to the left \
+                     of the lower right corner, {:?}",
+                    name, ul, lr
+                ));
+            }
+        }
+
+        let (portrait_ul, portrait_lr) = self.portrait_bounds;
+        let (landscape_ul, landscape_lr) = self.landscape_bounds;
+        let portrait = &portrait_lr - &portrait_ul;
+        let landscape = &landscape_lr - &landscape_ul;
+        if portrait.x > portrait.y {
+            problems.push("the portrait easel is wider than it is tall".to_string());
+        }
+        if landscape.y > landscape.x {
+            problems.push("the landscape easel is taller than it is wide".to_string());
+        }
+        if landscape.x <= portrait.x {
+            problems.push("the landscape easel isn't wider than the portrait easel".to_string());
+        }
+
+        if self.color_row_step == 0 {
+            problems.push("the palette has no distance between its columns".to_string());
+        }
+        if self.color_col_step == 0 {
+            problems.push("the palette has no distance between its rows".to_string());
+        }
+
+        if self.brush_widths.len() != NUM_BRUSH_STEPS as usize + 1 {
+            problems.push(format!(
+                "there should be {} brush widths but there are {}",
+                NUM_BRUSH_STEPS + 1,
+                self.brush_widths.len()
+            ));
+        } else if self.brush_widths.windows(2).any(|w| w[0] > w[1]) {
+            problems.push("the brush widths get smaller as the brush gets larger".to_string());
+        }
+
+        // Every element should be found where it was configured, or clicking on it will hit
+        // the canvas or another element instead. The swatches can't be told apart without a
+        // distance between them, which has already been reported.
+        let palette_spaced = self.color_row_step != 0 && self.color_col_step != 0;
+        for (name, coord, element) in self.elements() {
+            if let EaselElement::Color(_) = element {
+                if !palette_spaced {
+                    continue;
+                }
+            }
+            for (easel_name, orientation) in orientations.iter() {
+                match self.element_at(*orientation, coord) {
+                    Some(found) if found == element => (),
+                    Some(EaselElement::Canvas) => problems.push(format!(
+                        "the {} at {:?} is on the {} easel",
+                        name, coord, easel_name
+                    )),
+                    Some(found) => problems.push(format!(
+                        "the {} at {:?} is in the way of {:?}",
+                        name, coord, found
+                    )),
+                    None => problems.push(format!("the {} at {:?} can't be found", name, coord)),
+                }
+            }
+        }
+
+        problems.dedup();
+        problems
+    }
+
+    /// Lists every clickable element of the easel apart from the canvas, with a name and its
+    /// screen coordinates.
+    pub fn elements(&self) -> Vec<(String, Coord, EaselElement)> {
+        let mut elements = vec![
+            (
+                "paintbrush".to_string(),
+                self.paintbrush,
+                EaselElement::Tool(Tool::Paintbrush),
+            ),
+            (
+                "spray can".to_string(),
+                self.spray_can,
+                EaselElement::Tool(Tool::Spraycan),
+            ),
+            ("pen".to_string(), self.pen, EaselElement::Tool(Tool::Pen)),
+            (
+                "decrease brush button".to_string(),
+                self.decrease_brush,
+                EaselElement::DecreaseBrush,
+            ),
+            (
+                "increase brush button".to_string(),
+                self.increase_brush,
+                EaselElement::IncreaseBrush,
+            ),
+            (
+                "change orientation button".to_string(),
+                self.change_orientation,
+                EaselElement::ChangeOrientation,
+            ),
+        ];
+        for color in palette_in_use().iter().map(|entry| entry.name) {
+            elements.push((
+                format!("{:?} swatch", color),
+                self.color_coord(&color),
+                EaselElement::Color(color),
+            ));
+        }
+        elements
+    }
+
+    /// Returns the screen coordinates of the palette swatch for the given color.
+    pub fn color_coord(&self, color: &PaletteColor) -> Coord {
+        let color_pos = color.get_row_col();
+        Coord::new(
+            self.color_start.x + (color_pos.x * self.color_row_step),
+            self.color_start.y + (color_pos.y * self.color_col_step),
+        )
+    }
+
+    /// Works out which element of the easel lies under the given screen coordinate. The canvas
+    /// takes priority, followed by the buttons and finally the palette, which matches any
+    /// point within half a step of a swatch.
+    pub fn element_at(&self, orientation: Orientation, pos: Coord) -> Option<EaselElement> {
+        let (ul, lr) = self.bounds(orientation);
+        if pos.x >= ul.x && pos.x <= lr.x && pos.y >= ul.y && pos.y <= lr.y {
+            return Some(EaselElement::Canvas);
+        }
+
+        let buttons = [
+            (self.paintbrush, EaselElement::Tool(Tool::Paintbrush)),
+            (self.spray_can, EaselElement::Tool(Tool::Spraycan)),
+            (self.pen, EaselElement::Tool(Tool::Pen)),
+            (self.decrease_brush, EaselElement::DecreaseBrush),
+            (self.increase_brush, EaselElement::IncreaseBrush),
+            (self.change_orientation, EaselElement::ChangeOrientation),
+        ];
+        for (coord, element) in buttons.iter() {
+            if coord.x == pos.x && coord.y == pos.y {
+                return Some(*element);
+            }
+        }
+
+        let (row_step, col_step) = (self.color_row_step, self.color_col_step);
+        if row_step == 0 || col_step == 0 {
+            return None;
+        }
+        let offset = &pos - &self.color_start;
+        let row = (offset.x as f32 / row_step as f32).round() as i32;
+        let col = (offset.y as f32 / col_step as f32).round() as i32;
+        let near_x = (offset.x - row * row_step).abs() * 2 <= row_step.abs();
+        let near_y = (offset.y - col * col_step).abs() * 2 <= col_step.abs();
+        if near_x && near_y {
+            PaletteColor::from_row_col(Coord::new(row, col)).map(EaselElement::Color)
+        } else {
+            None
+        }
+    }
+}
+
+/// The elements of the easel that can be clicked on.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EaselElement {
+    Canvas,
+    Tool(Tool),
+    DecreaseBrush,
+    IncreaseBrush,
+    ChangeOrientation,
+    Color(PaletteColor),
 }
 
-#[derive(PartialEq)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 /// An enumeration that describes the two orientations the canvas can be.
 pub enum Orientation {
     Portrait,
@@ -53,9 +344,8 @@ pub enum Orientation {
 // Though we don't currently use the Pen or Spraycan options, we're leaving them in the enumeration
 // for completeness.
 #[allow(dead_code)]
-
 /// A list of the tools that Passpartout provides for drawing.
-#[derive(PartialEq)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 pub enum Tool {
     Paintbrush,
     Pen,
@@ -63,7 +353,16 @@ pub enum Tool {
 }
 
 /// The number of brush steps we can take when resizing.
-const NUM_BRUSH_STEPS: i32 = 16;
+pub const NUM_BRUSH_STEPS: i32 = 16;
+
+/// A rough guess at how many pixels wide the brush paints at each brush size, used until the
+/// brush widths have been measured with `--calibrate-brushes`. These aren't measurements: the
+/// smallest brush is taken to paint a single pixel, since images are drawn with it one row of
+/// pixels at a time, and every step up is guessed to add 3 pixels. This is the width of a
+/// single line, not how far apart lines of a fill are; see `plan::plan_fill` for that.
+pub const BRUSH_WIDTHS: [u32; NUM_BRUSH_STEPS as usize + 1] = [
+    1, 4, 7, 10, 13, 16, 19, 22, 25, 28, 31, 34, 37, 40, 43, 46, 49,
+];
 
 /// From a fresh boot of the game, the brush color starts as black.
 const STARTING_COLOR: PaletteColor = PaletteColor::Black;
@@ -71,36 +370,25 @@ const STARTING_COLOR: PaletteColor = PaletteColor::Black;
 /// From a fresh boot of the game, the paintbrush is the active tool.
 const STARTING_TOOL: Tool = Tool::Paintbrush;
 
-#[derive(Debug)]
-/// A list of potential errors that can occur while drawing to the easel.
-pub enum EaselError {
-    OutOfBounds,
-    NoCoord,
-}
-
-impl std::fmt::Display for EaselError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            EaselError::OutOfBounds => write!(f, "out of bounds"),
-            EaselError::NoCoord => write!(f, "invalid coordinates"),
-        }
-    }
-}
-
-impl Error for EaselError {}
-
 /// A structure keeping track of the current state of the easel and provides means to change
 /// the state of the easel via mouse clicks.
-pub struct Easel {
-    /// The object for manipulating the mouse.
-    pub mouse: Enigo,
+pub struct Easel<B: PointerBackend = Enigo> {
+    /// The backend used for manipulating the mouse.
+    pub mouse: B,
 
     /// The amount of time to wait between mouse moves and clicks.
     pub mouse_wait: Duration,
 
-    /// A mapping of where elements of the easel are in screen coordinates.
+    /// A mapping of where elements of the easel are, in screen coordinates or relative to the
+    /// game window.
     pub easel_coords: EaselCoords,
 
+    /// Where to find the game window when the coordinates are relative to it.
+    pub window: Option<Box<dyn WindowProvider>>,
+
+    /// Where the game window was last found, added to every coordinate before moving the mouse.
+    pub window_origin: Coord,
+
     /// The current orientation of the easel: landscape or portrait.
     pub orientation: Orientation,
 
@@ -112,9 +400,17 @@ pub struct Easel {
 
     /// The active tool for drawing.
     pub current_tool: Tool,
+
+    /// Whether strokes that leave the easel are clipped to it rather than refused. Off by
+    /// default, since a stroke off the easel usually means the configuration or plan is wrong.
+    pub clip_strokes: bool,
+
+    /// Set from another thread to stop the drawing as soon as possible, even partway through a
+    /// stroke. Once set, every operation fails with `PrinterError::Aborted`.
+    pub stop: Arc<AtomicBool>,
 }
 
-impl Easel {
+impl<B: PointerBackend> Easel<B> {
     /// Create a new easel. When creating a new easel, it's important to set the mouse wait
     /// properly. If you start seeing lines being drawn from the easel towards the color palette,
     /// you likely need to increase the mouse wait time.
@@ -126,21 +422,77 @@ impl Easel {
     /// # Arguments
     ///
     /// * `path`: Path to the JSON file containing the coordinates of easel elements in-game.
-    /// * `mouse`: An Enigo structure used to manipulate the mouse position.
+    /// * `mouse`: The backend used to manipulate the mouse position, such as an Enigo structure.
     /// * `mouse_wait`: The time to wait between mouse operations.
     ///
-    pub fn new(path: String, mouse: Enigo, mouse_wait: Duration) -> Result<Easel, Box<dyn Error>> {
+    pub fn new(path: String, mouse: B, mouse_wait: Duration) -> Result<Easel<B>, PrinterError> {
         let easel_coords = EaselCoords::new(path)?;
+        Ok(Easel::with_coords(easel_coords, mouse, mouse_wait))
+    }
+
+    /// Create a new easel from coordinates that have already been loaded. See `Easel::new` for
+    /// details on choosing the mouse wait.
+    pub fn with_coords(easel_coords: EaselCoords, mouse: B, mouse_wait: Duration) -> Easel<B> {
+        Easel::with_origin(easel_coords, mouse, mouse_wait, None, Coord::new(0, 0))
+    }
+
+    /// Create a new easel whose coordinates are relative to the game window. The window is
+    /// looked up again before every operation, so drawing follows the window if it's moved.
+    /// See `Easel::new` for details on choosing the mouse wait.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use enigo::*;
+    /// use passpartout_printer::{easel::{Easel, EaselCoords}, window::find_window_by_title};
+    /// use std::time::Duration;
+    /// use std::error::Error;
+    ///
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let easel_coords = EaselCoords::new("coords.json".into())?;
+    /// let window = find_window_by_title("Passpartout")?;
+    /// let mouse_wait = Duration::from_millis(7);
+    /// let easel = Easel::with_window(easel_coords, Enigo::new(), mouse_wait, window)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_window(
+        easel_coords: EaselCoords,
+        mouse: B,
+        mouse_wait: Duration,
+        mut window: Box<dyn WindowProvider>,
+    ) -> Result<Easel<B>, PrinterError> {
+        let origin = window.origin().map_err(PrinterError::Backend)?;
+        Ok(Easel::with_origin(
+            easel_coords,
+            mouse,
+            mouse_wait,
+            Some(window),
+            origin,
+        ))
+    }
+
+    fn with_origin(
+        easel_coords: EaselCoords,
+        mouse: B,
+        mouse_wait: Duration,
+        window: Option<Box<dyn WindowProvider>>,
+        window_origin: Coord,
+    ) -> Easel<B> {
         let orientation = Orientation::Portrait;
 
         let mut easel = Easel {
             mouse,
             mouse_wait,
             easel_coords,
+            window,
+            window_origin,
             orientation,
             brush_size: 0,
             current_color: STARTING_COLOR,
             current_tool: STARTING_TOOL,
+            clip_strokes: false,
+            stop: Arc::new(AtomicBool::new(false)),
         };
 
         // Since this application could be run multiple times in succession,
@@ -157,22 +509,28 @@ impl Easel {
         easel.change_color(&STARTING_COLOR);
         println!("Easel configuration complete.");
 
-        Ok(easel)
+        easel
     }
 
     fn click_custom_wait(&mut self, wait: Duration) {
-        self.mouse.mouse_down(MouseButton::Left);
-        thread::sleep(wait);
-        self.mouse.mouse_up(MouseButton::Left);
-        thread::sleep(wait);
+        self.mouse.press();
+        self.mouse.sleep(wait);
+        self.mouse.release();
+        self.mouse.sleep(wait);
     }
 
     fn click(&mut self) {
         self.click_custom_wait(self.mouse_wait);
     }
 
+    /// Moves the mouse to a position given in the easel's coordinates.
+    fn move_to(&mut self, coord: &Coord) {
+        let screen = coord + &self.window_origin;
+        self.mouse.move_to(screen.x, screen.y);
+    }
+
     fn move_and_click(&mut self, coord: &Coord) {
-        self.mouse.mouse_move_to(coord.x, coord.y);
+        self.move_to(coord);
         self.click();
     }
 
@@ -197,25 +555,31 @@ impl Easel {
         self.current_tool = tool;
     }
 
-    /// Returns the current bounds of the easel in screen coordinates.
+    /// Returns the current bounds of the easel as configured, which are relative to the game
+    /// window if the configuration names one. Use `screen_bounds` or `screen_coords` for
+    /// positions on the screen.
     pub fn get_bounds(&self) -> (Coord, Coord) {
-        match self.orientation {
-            Orientation::Portrait => self.easel_coords.portrait_bounds,
-            Orientation::Landscape => self.easel_coords.landscape_bounds,
-        }
+        self.easel_coords.bounds(self.orientation)
+    }
+
+    /// Returns the current bounds of the easel on the screen, wherever the game window was last
+    /// found.
+    pub fn screen_bounds(&self) -> (Coord, Coord) {
+        let (ul, lr) = self.get_bounds();
+        (ul + &self.window_origin, lr + &self.window_origin)
+    }
+
+    /// Returns the easel's coordinates as positions on the screen, wherever the game window was
+    /// last found.
+    pub fn screen_coords(&self) -> EaselCoords {
+        self.easel_coords.translated(self.window_origin)
     }
 
     /// Changes from the current color to the desired color. Does nothing if the current color is
     /// the same as the desired color.
     pub fn change_color(&mut self, color: &PaletteColor) {
         if *color != self.current_color {
-            let color_pos = color.get_row_col();
-            let row_step = self.easel_coords.color_row_step;
-            let col_step = self.easel_coords.color_col_step;
-            let color_coords = Coord::new(
-                self.easel_coords.color_start.x + (color_pos.x * row_step),
-                self.easel_coords.color_start.y + (color_pos.y * col_step),
-            );
+            let color_coords = self.easel_coords.color_coord(color);
             self.move_and_click(&color_coords);
             self.current_color = *color;
         }
@@ -249,7 +613,7 @@ impl Easel {
 
         // Make sure that we aren't going to accidentally set an internal
         // brush size greater or smaller than what the game supports.
-        let brush_size = brush_size.max(0).min(NUM_BRUSH_STEPS);
+        let brush_size = brush_size.clamp(0, NUM_BRUSH_STEPS);
         let brush_coords = if brush_size > self.brush_size {
             self.easel_coords.increase_brush
         } else {
@@ -300,7 +664,7 @@ impl Easel {
         start_line: Coord,
         end_line: Coord,
         color: &PaletteColor,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), PrinterError> {
         // Translate the coordinates of the picture to coordinates of the easel.
         self.draw_shape(&[start_line, end_line], color, false, false)
     }
@@ -321,106 +685,238 @@ impl Easel {
         color: &PaletteColor,
         close_shape: bool,
         fill: bool,
-    ) -> Result<(), Box<dyn Error>> {
-        let (start, end) = self.get_bounds();
-        self.change_color(color);
-
-        let start_point = match points.get(0) {
-            Some(p) => p,
-            None => Err(EaselError::NoCoord)?,
-        };
+    ) -> Result<(), PrinterError> {
+        let fill_width = self.easel_coords.brush_width(0);
+        let plan = plan_shape(
+            points,
+            color,
+            close_shape,
+            fill,
+            self.brush_size,
+            fill_width,
+        )?;
+        self.execute_plan(&plan)
+    }
 
-        let start_point = start + start_point;
-        if start_point.x > end.x || start_point.y > end.y {
-            println!("point: {:?}, end: {:?}", start_point, end);
-            Err(EaselError::OutOfBounds)?
+    /// Carries out every operation of a plan in order.
+    pub fn execute_plan(&mut self, plan: &DrawPlan) -> Result<(), PrinterError> {
+        for op in plan.ops.iter() {
+            self.execute(op)?;
         }
+        Ok(())
+    }
 
-        self.mouse.mouse_move_to(start_point.x, start_point.y);
-        thread::sleep(self.mouse_wait);
-        self.mouse.mouse_down(MouseButton::Left);
-        for point in points.iter() {
-            let point = start + point;
-            if point.x > end.x || point.y > end.y {
-                println!("point: {:?}, end: {:?}", point, end);
-                Err(EaselError::OutOfBounds)?
+    /// Carries out a single operation of a plan.
+    pub fn execute(&mut self, op: &DrawOp) -> Result<(), PrinterError> {
+        if self.stop.load(Ordering::SeqCst) {
+            Err(PrinterError::Aborted)?
+        }
+        if let Some(window) = self.window.as_mut() {
+            self.window_origin = window.origin().map_err(PrinterError::Backend)?;
+        }
+        match op {
+            DrawOp::SelectTool(tool) => self.change_tool(*tool),
+            DrawOp::SetBrush(brush_size) => self.change_brush_size(*brush_size),
+            DrawOp::SetColor(color) => self.change_color(color),
+            DrawOp::Stroke(points) => self.stroke(points)?,
+            DrawOp::SetOrientation(orientation) => {
+                if self.orientation != *orientation {
+                    self.change_orientation();
+                }
             }
-            self.mouse.mouse_move_to(point.x, point.y);
-            thread::sleep(self.mouse_wait);
         }
+        Ok(())
+    }
 
-        if close_shape || fill {
-            self.mouse.mouse_move_to(start_point.x, start_point.y);
-            thread::sleep(self.mouse_wait);
+    /// Drags the mouse through the given points, in image coordinates, with the current color
+    /// and brush. Points off the easel are an error unless `clip_strokes` is set, in which case
+    /// only the parts of the stroke on the easel are drawn.
+    fn stroke(&mut self, points: &[Coord]) -> Result<(), PrinterError> {
+        let bounds = self.get_bounds();
+        let (start, end) = bounds;
+
+        let points: Vec<Coord> = points.iter().map(|point| start + point).collect();
+        if points.is_empty() {
+            Err(PrinterError::InvalidPlan(
+                "a stroke has no points".to_string(),
+            ))?
         }
 
-        self.mouse.mouse_up(MouseButton::Left);
-        thread::sleep(self.mouse_wait);
-
-        if fill {
-            self.fill(points, color)?;
+        if self.clip_strokes {
+            for piece in clip_stroke(&points, start, end) {
+                self.drag(&piece)?;
+            }
+            return Ok(());
         }
 
-        Ok(())
+        // Check the whole stroke before pressing the button, so a bad point can't leave the
+        // mouse held down partway through.
+        for point in points.iter() {
+            if point.x < start.x || point.y < start.y || point.x > end.x || point.y > end.y {
+                Err(PrinterError::OutOfBounds {
+                    point: *point,
+                    bounds,
+                })?
+            }
+        }
+        self.drag(&points)
     }
 
-    /// Use the scanline polygon fill algorithm to fill in the polygon.
-    ///
-    /// * `points` List of coordinates that define the polygon to fill.
-    /// * `color` The color to fill the polygon with.
-    ///
-    fn fill(&mut self, points: &[Coord], color: &PaletteColor) -> Result<(), Box<dyn Error>> {
-        let mut edges: Vec<[&Coord; 2]> = points.windows(2).map(|pts| [&pts[0], &pts[1]]).collect();
-        edges.push([points.first().unwrap(), points.last().unwrap()]);
-        let slope: Vec<f32> = edges
+    /// Holds the mouse button down while moving through the given points, in the easel's
+    /// coordinates. The points must all be on the easel. The button is let go however the
+    /// stroke ends, and the stroke ends early if the drawing is stopped.
+    fn drag(&mut self, points: &[Coord]) -> Result<(), PrinterError> {
+        let points: Vec<Coord> = points
             .iter()
-            .map(|pts| {
-                if pts[1].x == pts[0].x {
-                    0.0
-                } else {
-                    (pts[1].y - pts[0].y) as f32 / (pts[1].x - pts[0].x) as f32
-                }
-            })
+            .map(|point| point + &self.window_origin)
             .collect();
-        let start_y = points.iter().fold(i32::MAX, |acc, pnt| acc.min(pnt.y));
-        let end_y = points.iter().fold(0, |acc, pnt| acc.max(pnt.y));
-        let mut iy = start_y;
-        let old_brush_size = self.brush_size;
-        self.change_brush_size(0);
-
-        while iy < end_y {
-            let mut active_edges = vec![];
-            for (edge, slope) in edges.iter().zip(slope.iter()) {
-                let max = edge[0].y.max(edge[1].y);
-                let min = edge[0].y.min(edge[1].y);
-                if max > iy && min < iy && max != min {
-                    active_edges.push((edge, slope));
-                }
-            }
-            let x_draw: Vec<i32> = active_edges
-                .iter()
-                .map(|&(pts, m)| {
-                    if *m == 0.0 {
-                        pts[0].x
-                    } else {
-                        (pts[0].x as f32 + 1.0 / m * (iy - pts[0].y) as f32) as i32
-                    }
-                })
-                .collect();
-            let mut in_poly = true;
-            for x in x_draw.windows(2) {
-                if x[0] != x[1] && in_poly {
-                    self.draw_line(Coord::new(x[0], iy), Coord::new(x[1], iy), color)?;
-                }
-                in_poly = !in_poly;
-            }
+        let start_point = match points.first() {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        self.mouse.move_to(start_point.x, start_point.y);
+        self.mouse.sleep(self.mouse_wait);
 
-            // Since the brush size is 0, we increment by half of the brush
-            // size, or 6 pixels.
-            iy += 6;
+        let held = Held::press(&mut self.mouse);
+        for point in points.iter() {
+            if self.stop.load(Ordering::SeqCst) {
+                Err(PrinterError::Aborted)?
+            }
+            held.mouse.move_to(point.x, point.y);
+            held.mouse.sleep(self.mouse_wait);
         }
+        drop(held);
 
-        self.change_brush_size(old_brush_size);
+        self.mouse.sleep(self.mouse_wait);
         Ok(())
     }
 }
+
+/// Holds the mouse button down until it's dropped, so the button is let go however a stroke
+/// ends, including by an error or a panic.
+struct Held<'a, B: PointerBackend> {
+    mouse: &'a mut B,
+}
+
+impl<'a, B: PointerBackend> Held<'a, B> {
+    fn press(mouse: &'a mut B) -> Held<'a, B> {
+        mouse.press();
+        Held { mouse }
+    }
+}
+
+impl<B: PointerBackend> Drop for Held<'_, B> {
+    fn drop(&mut self) {
+        self.mouse.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulated::tests::{coords, easel};
+
+    fn assert_problem(coords: &EaselCoords, expected: &str) {
+        let problems = coords.check();
+        assert!(
+            problems.iter().any(|problem| problem == expected),
+            "{:?} doesn't include {:?}",
+            problems,
+            expected
+        );
+    }
+
+    #[test]
+    fn a_valid_configuration_has_no_problems() {
+        assert_eq!(coords().check(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn swapped_corners_are_a_problem() {
+        let mut coords = coords();
+        let (ul, lr) = coords.portrait_bounds;
+        coords.portrait_bounds = (lr, ul);
+        assert_problem(
+            &coords,
+            "the upper left corner of the portrait easel, Coord { x: 300, y: 400 }, isn't above \
+             and to the left of the lower right corner, Coord { x: 100, y: 100 }",
+        );
+    }
+
+    #[test]
+    fn the_landscape_easel_must_be_wider() {
+        let mut coords = coords();
+        coords.landscape_bounds = (Coord::new(100, 150), Coord::new(300, 350));
+        assert_eq!(
+            coords.check(),
+            vec!["the landscape easel isn't wider than the portrait easel".to_string()]
+        );
+    }
+
+    #[test]
+    fn the_palette_needs_a_step() {
+        let mut coords = coords();
+        coords.color_col_step = 0;
+        assert_eq!(
+            coords.check(),
+            vec!["the palette has no distance between its rows".to_string()]
+        );
+    }
+
+    #[test]
+    fn a_button_on_the_canvas_is_a_problem() {
+        let mut coords = coords();
+        coords.pen = Coord::new(200, 200);
+        assert_problem(
+            &coords,
+            "the pen at Coord { x: 200, y: 200 } is on the portrait easel",
+        );
+        assert_problem(
+            &coords,
+            "the pen at Coord { x: 200, y: 200 } is on the landscape easel",
+        );
+    }
+
+    #[test]
+    fn brush_widths_must_not_shrink() {
+        let mut coords = coords();
+        coords.brush_widths = BRUSH_WIDTHS.to_vec();
+        coords.brush_widths.swap(3, 4);
+        assert_eq!(
+            coords.check(),
+            vec!["the brush widths get smaller as the brush gets larger".to_string()]
+        );
+    }
+
+    #[test]
+    fn scales_to_another_resolution() {
+        let scaled = coords().scaled((1920, 1080), (2560, 1440));
+        assert_eq!(
+            scaled.portrait_bounds,
+            (Coord::new(133, 133), Coord::new(400, 533))
+        );
+        assert_eq!(scaled.increase_brush, Coord::new(13, 120));
+        assert_eq!(scaled.color_row_step, 27);
+        assert_eq!(scaled.color_col_step, 27);
+        assert_eq!(scaled.brush_widths[0], 1);
+        assert_eq!(scaled.brush_widths[1], 5);
+        assert_eq!(scaled.brush_widths[NUM_BRUSH_STEPS as usize], 65);
+        assert_eq!(scaled.screen_size, Some((2560, 1440)));
+    }
+
+    #[test]
+    fn a_stroke_off_the_easel_is_refused_before_pressing() {
+        let mut easel = easel();
+        let presses = easel.mouse.estimate().presses;
+        let stroke = DrawOp::Stroke(Coord::from_slice(&[(10, 10), (-5, 10)]));
+        match easel.execute(&stroke) {
+            Err(PrinterError::OutOfBounds { point, bounds }) => {
+                assert_eq!(point, Coord::new(95, 110));
+                assert_eq!(bounds, easel.get_bounds());
+            }
+            result => panic!("expected the stroke to be out of bounds, got {:?}", result),
+        }
+        assert_eq!(easel.mouse.estimate().presses, presses);
+        assert!(!easel.mouse.is_pressed());
+    }
+}
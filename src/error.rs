@@ -0,0 +1,96 @@
+use crate::config::ConfigError;
+use crate::coords::Coord;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+/// The errors that can occur while configuring, planning or drawing.
+pub enum PrinterError {
+    /// A file couldn't be read or written.
+    Io(io::Error),
+
+    /// The configuration couldn't be loaded or saved.
+    Config(ConfigError),
+
+    /// The image to draw couldn't be opened or decoded.
+    Image(image::ImageError),
+
+    /// A stroke went outside the easel. `point` and `bounds` are in screen coordinates, or
+    /// relative to the game window if the configuration is.
+    OutOfBounds {
+        point: Coord,
+        bounds: (Coord, Coord),
+    },
+
+    /// A plan, or a shape to be planned, doesn't make sense.
+    InvalidPlan(String),
+
+    /// The backend moving the mouse or finding the window failed.
+    Backend(Box<dyn Error>),
+
+    /// The user stopped the drawing.
+    Aborted,
+}
+
+impl PrinterError {
+    /// The exit code the application finishes with after this error. Aborting follows the
+    /// convention for being stopped with Ctrl+C.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            PrinterError::Config(_) => 2,
+            PrinterError::Image(_) => 3,
+            PrinterError::InvalidPlan(_) => 4,
+            PrinterError::Aborted => 130,
+            _ => 1,
+        }
+    }
+}
+
+impl fmt::Display for PrinterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrinterError::Io(e) => write!(f, "{}", e),
+            PrinterError::Config(e) => write!(f, "{}", e),
+            PrinterError::Image(e) => write!(f, "couldn't read the image: {}", e),
+            PrinterError::OutOfBounds { point, bounds } => write!(
+                f,
+                "the point ({}, {}) is outside the easel, which runs from ({}, {}) to ({}, {})",
+                point.x, point.y, bounds.0.x, bounds.0.y, bounds.1.x, bounds.1.y
+            ),
+            PrinterError::InvalidPlan(reason) => write!(f, "invalid plan: {}", reason),
+            PrinterError::Backend(e) => write!(f, "{}", e),
+            PrinterError::Aborted => write!(f, "the drawing was stopped"),
+        }
+    }
+}
+
+impl Error for PrinterError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PrinterError::Io(e) => Some(e),
+            PrinterError::Config(e) => Some(e),
+            PrinterError::Image(e) => Some(e),
+            PrinterError::Backend(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PrinterError {
+    fn from(e: io::Error) -> PrinterError {
+        PrinterError::Io(e)
+    }
+}
+
+impl From<ConfigError> for PrinterError {
+    fn from(e: ConfigError) -> PrinterError {
+        PrinterError::Config(e)
+    }
+}
+
+impl From<image::ImageError> for PrinterError {
+    fn from(e: image::ImageError) -> PrinterError {
+        PrinterError::Image(e)
+    }
+}
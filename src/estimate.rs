@@ -0,0 +1,205 @@
+use crate::backend::PointerBackend;
+use crate::coords::Coord;
+use crate::easel::{EaselCoords, EaselElement, Orientation};
+use std::time::Duration;
+
+/// A tally of the mouse operations needed for a drawing and how long they take.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Estimate {
+    /// The number of times the mouse is moved.
+    pub moves: usize,
+
+    /// The number of times the left mouse button is pressed, which includes both clicks on the
+    /// easel's buttons and strokes on the canvas.
+    pub presses: usize,
+
+    /// The number of strokes dragged across the canvas.
+    pub strokes: usize,
+
+    /// The number of clicks on the palette.
+    pub color_changes: usize,
+
+    /// The number of clicks on the buttons to increase or decrease the brush size.
+    pub brush_changes: usize,
+
+    /// The number of clicks on the tool buttons.
+    pub tool_changes: usize,
+
+    /// The number of clicks on the orientation button.
+    pub orientation_changes: usize,
+
+    /// The number of presses that landed on no part of the easel at all.
+    pub missed_clicks: usize,
+
+    /// The total time spent waiting between mouse operations, which is effectively the time
+    /// the drawing takes.
+    pub duration: Duration,
+}
+
+impl Estimate {
+    /// The total number of mouse operations: every move, press and release.
+    pub fn mouse_operations(&self) -> usize {
+        self.moves + self.presses * 2
+    }
+}
+
+/// A backend that never touches the mouse and instead counts what would have been done to it.
+/// Drawing onto an easel backed by an `Estimator` is a fast way of finding out how long the
+/// drawing would take at the easel's mouse wait.
+///
+/// # Example
+///
+/// ```no_run
+/// use passpartout_printer::{
+///     colors::PaletteColor,
+///     coords::Coord,
+///     easel::{Easel, EaselCoords},
+///     estimate::Estimator,
+/// };
+/// use std::error::Error;
+/// use std::time::Duration;
+///
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let coords = EaselCoords::new("coords.json".into())?;
+/// let estimator = Estimator::new(&coords);
+/// let mut easel = Easel::with_coords(coords, estimator, Duration::from_millis(7));
+/// easel.draw_line(Coord::new(0, 0), Coord::new(100, 100), &PaletteColor::Red)?;
+/// println!("{:?}", easel.mouse.estimate);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Estimator {
+    easel_coords: EaselCoords,
+    orientation: Orientation,
+    cursor: Coord,
+    pressed: bool,
+
+    /// The operations counted so far.
+    pub estimate: Estimate,
+}
+
+impl Estimator {
+    /// Creates an estimator for an easel laid out according to the given coordinates.
+    pub fn new(easel_coords: &EaselCoords) -> Estimator {
+        Estimator {
+            easel_coords: easel_coords.clone(),
+            orientation: Orientation::Portrait,
+            cursor: Coord::new(0, 0),
+            pressed: false,
+            estimate: Estimate::default(),
+        }
+    }
+
+    /// The orientation the estimated easel is currently in.
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// The element of the easel under the cursor.
+    pub fn element_under_cursor(&self) -> Option<EaselElement> {
+        self.easel_coords.element_at(self.orientation, self.cursor)
+    }
+}
+
+impl PointerBackend for Estimator {
+    fn move_to(&mut self, x: i32, y: i32) {
+        self.cursor = Coord::new(x, y);
+        self.estimate.moves += 1;
+    }
+
+    fn press(&mut self) {
+        if self.pressed {
+            return;
+        }
+        self.pressed = true;
+        self.estimate.presses += 1;
+        match self.element_under_cursor() {
+            Some(EaselElement::Canvas) => self.estimate.strokes += 1,
+            None => self.estimate.missed_clicks += 1,
+            Some(EaselElement::Tool(_)) => self.estimate.tool_changes += 1,
+            Some(EaselElement::DecreaseBrush) | Some(EaselElement::IncreaseBrush) => {
+                self.estimate.brush_changes += 1
+            }
+            Some(EaselElement::ChangeOrientation) => {
+                self.estimate.orientation_changes += 1;
+                self.orientation = match self.orientation {
+                    Orientation::Portrait => Orientation::Landscape,
+                    Orientation::Landscape => Orientation::Portrait,
+                };
+            }
+            Some(EaselElement::Color(_)) => self.estimate.color_changes += 1,
+        }
+    }
+
+    fn release(&mut self) {
+        self.pressed = false;
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.estimate.duration += duration;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::PaletteColor;
+    use crate::easel::Easel;
+    use crate::plan::{DrawOp, DrawPlan};
+    use crate::simulated::tests::coords;
+
+    #[test]
+    fn counts_a_plan() {
+        let coords = coords();
+        let estimator = Estimator::new(&coords);
+        let mut easel = Easel::with_coords(coords, estimator, Duration::from_millis(7));
+        let before = easel.mouse.estimate;
+
+        let plan = DrawPlan {
+            ops: vec![
+                DrawOp::SetColor(PaletteColor::Red),
+                DrawOp::SetBrush(3),
+                DrawOp::Stroke(vec![Coord::new(0, 0), Coord::new(10, 0)]),
+                DrawOp::Stroke(vec![
+                    Coord::new(0, 5),
+                    Coord::new(10, 5),
+                    Coord::new(10, 15),
+                ]),
+            ],
+        };
+        easel.execute_plan(&plan).unwrap();
+        let after = easel.mouse.estimate;
+
+        assert_eq!(after.strokes - before.strokes, 2);
+        assert_eq!(after.color_changes - before.color_changes, 1);
+        assert_eq!(after.brush_changes - before.brush_changes, 3);
+        assert_eq!(after.missed_clicks, 0);
+        // One move and click for the color, one move and three clicks for the brush, then a
+        // move to the start of each stroke and a move to each of its points.
+        assert_eq!(
+            after.mouse_operations() - before.mouse_operations(),
+            9 + 6 * 2
+        );
+        // The first brush click waits 7 ms after the press and again after the release, the
+        // other two wait 32 ms each time. Each stroke waits before pressing, at every point
+        // and after letting go.
+        let color = 2 * 7;
+        let brush = 2 * 7 + 2 * 2 * 32;
+        let strokes = (2 + 2) * 7 + (3 + 2) * 7;
+        assert_eq!(
+            after.duration - before.duration,
+            Duration::from_millis(color + brush + strokes)
+        );
+    }
+
+    #[test]
+    fn a_press_off_the_easel_is_not_a_stroke() {
+        let mut estimator = Estimator::new(&coords());
+        estimator.move_to(1000, 1000);
+        estimator.press();
+        estimator.release();
+        assert_eq!(estimator.estimate.presses, 1);
+        assert_eq!(estimator.estimate.strokes, 0);
+        assert_eq!(estimator.estimate.missed_clicks, 1);
+    }
+}
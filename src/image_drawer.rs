@@ -1,20 +1,91 @@
-use crate::colors::{Palette, PaletteColor};
+use crate::colors::{ColorMetric, Palette, PaletteColor};
 use crate::coords::Coord;
-use crate::easel::{Easel, Orientation, Tool};
+use crate::dither::{dither, DitherOptions};
+use crate::easel::{EaselCoords, Orientation, Tool, NUM_BRUSH_STEPS};
+use crate::error::PrinterError;
+use crate::layers::Layer;
+use crate::plan::{DrawOp, DrawPlan};
 use image::imageops::ColorMap;
 use image::imageops::FilterType;
 use image::DynamicImage;
 use image::GenericImageView;
-use image::Rgba;
-use std::error::Error;
+use image::{Rgba, RgbaImage};
+use std::str::FromStr;
 
-/// A structure that provides means to draw whole images to the easel.
-pub struct ImageDrawer<'a> {
-    easel: &'a mut Easel,
+/// The order in which the lines making up an image are drawn.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DrawOrder {
+    /// Draw row by row from the top, changing color whenever the next run needs it.
+    Scanline,
+
+    /// Draw every run of one color across the whole image before moving on to the next,
+    /// starting with the lightest color so darker details are painted over it. This needs at
+    /// most one palette click per color.
+    ColorBatched,
+}
+
+impl FromStr for DrawOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DrawOrder, String> {
+        match s {
+            "scanline" => Ok(DrawOrder::Scanline),
+            "color" => Ok(DrawOrder::ColorBatched),
+            _ => Err(format!("unknown draw order '{}'", s)),
+        }
+    }
+}
+
+/// Options controlling how an image is planned.
+#[derive(Clone, Debug)]
+pub struct PlanOptions {
+    /// The order to draw the lines of the image in.
+    pub order: DrawOrder,
+
+    /// Whether to flood the whole easel, borders included, with the most common color of the
+    /// image using the largest brush, and then only draw the runs of other colors. When the
+    /// image is mostly white, this leaves the white borders with nothing left to draw.
+    pub fill_background: bool,
+
+    /// Whether to paint large areas of each color with large brushes, only using the smallest
+    /// brush for the details. Implies drawing in `DrawOrder::ColorBatched`.
+    pub multi_brush: bool,
+
+    /// How to pick the palette color closest to each pixel of the image.
+    pub metric: ColorMetric,
+}
+
+impl Default for PlanOptions {
+    fn default() -> PlanOptions {
+        PlanOptions {
+            order: DrawOrder::Scanline,
+            fill_background: false,
+            multi_brush: false,
+            metric: ColorMetric::default(),
+        }
+    }
+}
+
+/// A structure that plans out how to draw whole images to the easel.
+pub struct ImageDrawer {
+    plan: DrawPlan,
     palette: Palette,
-    current_color: PaletteColor,
+    options: PlanOptions,
+
+    // The lines making up the image, in the order they were found.
+    lines: Vec<Line>,
+
+    // How many pixels wide the brush paints at each brush size.
+    brush_widths: Vec<u32>,
+
+    // The color of the run of pixels currently being gathered.
+    current_color: Option<PaletteColor>,
+
+    // The color the image has been flooded with, if any, and the corners of the area the flood
+    // is sure to have covered.
+    background: Option<(PaletteColor, Coord, Coord)>,
 
-    // The size of the easel along x.
+    // The position of the lower-right corner of the easel, relative to its upper-left corner.
     easel_size: Coord,
 
     // The starting point to use for the next draw operation.
@@ -27,36 +98,113 @@ pub struct ImageDrawer<'a> {
     offset: Coord,
 }
 
+/// A single stroke of the image.
+struct Line {
+    color: PaletteColor,
+    brush_size: i32,
+    points: Vec<Coord>,
+}
+
 /// A helper function for scaling images to the dimensions of the easel prior to drawing.
-pub fn size_to_easel(image: &DynamicImage, easel: &Easel) -> DynamicImage {
+pub fn size_to_easel(image: &DynamicImage, easel_coords: &EaselCoords) -> DynamicImage {
     let (size_x, size_y) = image.dimensions();
     let (ul_corner, br_corner) = if size_x > size_y {
-        easel.easel_coords.landscape_bounds
+        easel_coords.landscape_bounds
     } else {
-        easel.easel_coords.portrait_bounds
+        easel_coords.portrait_bounds
     };
     let x_bounds = br_corner.x - ul_corner.x;
     let y_bounds = br_corner.y - ul_corner.y;
     image.resize(x_bounds as u32, y_bounds as u32, FilterType::Lanczos3)
 }
 
-impl<'a> ImageDrawer<'a> {
-    pub fn new(easel: &'a mut Easel, size_x: u32, size_y: u32) -> ImageDrawer<'a> {
-        // For drawing images, we need the brush to be as small as possible.
-        easel.change_brush_size(0);
-        easel.change_tool(Tool::Paintbrush);
+/// Opens an image and prepares it for planning, scaling it to fit the easel and dithering it
+/// to the palette, matching colors with `metric`, if asked to.
+pub fn open_image(
+    path: &str,
+    easel_coords: &EaselCoords,
+    dither_options: Option<DitherOptions>,
+    enable_scale: bool,
+    metric: ColorMetric,
+) -> Result<RgbaImage, PrinterError> {
+    let image = image::open(path)?;
+    let mut image = if enable_scale {
+        size_to_easel(&image, easel_coords).into_rgba8()
+    } else {
+        image.into_rgba8()
+    };
+    if let Some(options) = dither_options {
+        dither(&mut image, &Palette::with_metric(metric), &options);
+    }
+    Ok(image)
+}
 
-        if (size_x > size_y && easel.orientation == Orientation::Portrait)
-            || (size_y > size_x && easel.orientation == Orientation::Landscape)
-        {
-            easel.change_orientation();
+/// Finds the palette color that the most pixels of the image map to with the given metric, or
+/// `None` if the image has no pixels.
+pub fn dominant_color(image: &RgbaImage, metric: ColorMetric) -> Option<PaletteColor> {
+    let palette = Palette::with_metric(metric);
+    let mut counts = vec![0usize; palette.colormap.len()];
+    for pixel in image.pixels() {
+        counts[palette.index_of(pixel)] += 1;
+    }
+    let (index, _) = counts
+        .iter()
+        .enumerate()
+        .filter(|&(_, count)| *count > 0)
+        .max_by_key(|&(_, count)| *count)?;
+    Some(palette.colormap[index])
+}
+
+/// Plans out the drawing of an entire image, including the white borders used to center it on
+/// the easel.
+pub fn plan_image(
+    image: &RgbaImage,
+    easel_coords: &EaselCoords,
+    options: &PlanOptions,
+) -> DrawPlan {
+    let (size_x, size_y) = image.dimensions();
+    let mut image_drawer = ImageDrawer::new(easel_coords, size_x, size_y, options);
+    if options.fill_background {
+        if let Some(color) = dominant_color(image, options.metric) {
+            image_drawer.fill_background(color);
+        }
+    }
+    if options.multi_brush {
+        image_drawer.draw_layers(image);
+    } else {
+        image_drawer.draw_top_border();
+        for (x, y, pixel) in image.enumerate_pixels() {
+            image_drawer.handle_pixel(pixel, x, y);
         }
+        image_drawer.cleanup_image();
+    }
+    image_drawer.into_plan()
+}
 
-        let current_color = easel.current_color;
+impl ImageDrawer {
+    pub fn new(
+        easel_coords: &EaselCoords,
+        size_x: u32,
+        size_y: u32,
+        options: &PlanOptions,
+    ) -> ImageDrawer {
+        let mut plan = DrawPlan::new();
+
+        // For drawing images, we need the brush to be as small as possible.
+        plan.push(DrawOp::SetBrush(0));
+        plan.push(DrawOp::SelectTool(Tool::Paintbrush));
 
-        let (ulcorner, lrcorner) = easel.get_bounds();
-        let easel_x = lrcorner.x - ulcorner.x - 1;
-        let easel_y = lrcorner.y - ulcorner.y - 1;
+        // Square images are drawn in portrait to match how size_to_easel scales them.
+        let orientation = if size_x > size_y {
+            Orientation::Landscape
+        } else {
+            Orientation::Portrait
+        };
+        plan.push(DrawOp::SetOrientation(orientation));
+
+        let (ulcorner, lrcorner) = easel_coords.bounds(orientation);
+        let easel_x = lrcorner.x - ulcorner.x;
+        let easel_y = lrcorner.y - ulcorner.y;
         let easel_size = Coord::new(easel_x, easel_y);
 
         let size_x = size_x as i32;
@@ -64,8 +212,8 @@ impl<'a> ImageDrawer<'a> {
         let image_size = Coord::new(size_x, size_y);
         //
         // Offsets used to center the image as best as possible on the easel.
-        let offset_x = (easel_x - size_x + 1) / 2;
-        let offset_y = (easel_y - size_y) / 2;
+        let offset_x = (easel_x - size_x) / 2;
+        let offset_y = (easel_y - size_y - 1) / 2;
         let offset = Coord::new(offset_x, offset_y);
 
         let start_x = offset_x;
@@ -73,9 +221,15 @@ impl<'a> ImageDrawer<'a> {
         let current_pos = Coord::new(start_x, start_y);
 
         ImageDrawer {
-            easel,
-            palette: Palette::new(),
-            current_color,
+            plan,
+            palette: Palette::with_metric(options.metric),
+            options: options.clone(),
+            lines: vec![],
+            brush_widths: (0..=NUM_BRUSH_STEPS)
+                .map(|brush_size| easel_coords.brush_width(brush_size))
+                .collect(),
+            current_color: None,
+            background: None,
             easel_size,
             image_size,
             current_pos,
@@ -83,20 +237,157 @@ impl<'a> ImageDrawer<'a> {
         }
     }
 
+    /// Consumes the drawer, returning the plan for every line gathered so far in the order
+    /// given by the plan options.
+    pub fn into_plan(mut self) -> DrawPlan {
+        if self.options.order == DrawOrder::ColorBatched || self.options.multi_brush {
+            // The sort is stable, so lines of the same color keep the order they were found in.
+            self.lines
+                .sort_by(|a, b| b.color.lightness().total_cmp(&a.color.lightness()));
+        }
+
+        // Every way of starting a plan leaves the brush at its smallest size.
+        let mut plan_color = None;
+        let mut plan_brush = 0;
+        for line in self.lines {
+            if plan_color != Some(line.color) {
+                self.plan.push(DrawOp::SetColor(line.color));
+                plan_color = Some(line.color);
+            }
+            if plan_brush != line.brush_size {
+                self.plan.push(DrawOp::SetBrush(line.brush_size));
+                plan_brush = line.brush_size;
+            }
+            self.plan.push(DrawOp::Stroke(line.points));
+        }
+        if plan_brush != 0 {
+            self.plan.push(DrawOp::SetBrush(0));
+        }
+        self.plan
+    }
+
+    /// Whether a point of the given color has already been painted by flooding the background.
+    fn flooded(&self, point: Coord, color: PaletteColor) -> bool {
+        match self.background {
+            Some((background, ul, lr)) => {
+                color == background
+                    && (ul.x..=lr.x).contains(&point.x)
+                    && (ul.y..=lr.y).contains(&point.y)
+            }
+            None => false,
+        }
+    }
+
+    /// Adds a line of the given color to the drawing. Lines in the background color are left
+    /// out where the background has already been painted.
+    fn draw_line(&mut self, start_line: Coord, end_line: Coord, color: PaletteColor) {
+        if !self.flooded(start_line, color) || !self.flooded(end_line, color) {
+            self.lines.push(Line {
+                color,
+                brush_size: 0,
+                points: vec![start_line, end_line],
+            });
+        }
+    }
+
+    /// Draws the whole easel, image and borders alike, one color at a time. Large areas of
+    /// each color are painted with large brushes and the smallest brush is only used for the
+    /// details. See `Layer::decompose` for how the brushes are picked.
+    pub fn draw_layers(&mut self, image: &RgbaImage) {
+        let width = (self.easel_size.x + 1).max(0) as usize;
+        let height = (self.easel_size.y + 1).max(0) as usize;
+        let white = self
+            .palette
+            .colormap
+            .iter()
+            .position(|color| *color == PaletteColor::White)
+            .unwrap();
+
+        // Work out the color of every pixel of the easel, with white around the image.
+        let mut grid = vec![white; width * height];
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let ix = x as i32 + self.offset.x;
+            let iy = y as i32 + self.offset.y;
+            if ix >= 0 && iy >= 0 && (ix as usize) < width && (iy as usize) < height {
+                grid[iy as usize * width + ix as usize] = self.palette.index_of(pixel);
+            }
+        }
+
+        for (index, color) in self.palette.colormap.clone().into_iter().enumerate() {
+            let mask: Vec<bool> = grid
+                .iter()
+                .enumerate()
+                .map(|(i, &pixel)| {
+                    let point = Coord::new((i % width) as i32, (i / width) as i32);
+                    pixel == index && !self.flooded(point, color)
+                })
+                .collect();
+            if !mask.contains(&true) {
+                continue;
+            }
+            let layer = Layer::new(mask, width, height);
+            for stroke in layer.decompose(&self.brush_widths) {
+                self.lines.push(Line {
+                    color,
+                    brush_size: stroke.brush_size,
+                    points: stroke.points,
+                });
+            }
+        }
+    }
+
+    /// Floods the whole easel with the given color using the largest brush, sweeping back and
+    /// forth in a single stroke from edge to edge. Lines of this color drawn afterwards are
+    /// skipped, including the white borders if the easel is flooded white.
+    pub fn fill_background(&mut self, color: PaletteColor) {
+        let brush_width = self.brush_widths[NUM_BRUSH_STEPS as usize] as i32;
+        let (right, bottom) = (self.easel_size.x, self.easel_size.y);
+        if right < 0 || bottom < 0 {
+            return;
+        }
+
+        // Overlap the sweeps a little so no gaps are left between them. The sweeps run along the
+        // very edges of the easel, where the game cuts the brush off at the edge.
+        let spacing = (brush_width * 3 / 4).max(1);
+        let mut sweep = vec![];
+        let mut iy = 0;
+        loop {
+            let iy_clamped = iy.min(bottom);
+            let (from, to) = if sweep.len() % 4 == 0 {
+                (0, right)
+            } else {
+                (right, 0)
+            };
+            sweep.push(Coord::new(from, iy_clamped));
+            sweep.push(Coord::new(to, iy_clamped));
+            if iy_clamped >= bottom {
+                break;
+            }
+            iy += spacing;
+        }
+
+        self.plan.push(DrawOp::SetBrush(NUM_BRUSH_STEPS));
+        self.plan.push(DrawOp::SetColor(color));
+        self.plan.push(DrawOp::Stroke(sweep));
+        self.plan.push(DrawOp::SetBrush(0));
+
+        // The sweeps are closer together than the brush is wide, so between them the brush
+        // reaches every pixel, right up to the edges of the easel.
+        self.background = Some((color, Coord::new(0, 0), Coord::new(right, bottom)));
+    }
+
     /// Draw the top white border for centering the image along the y-axis.
     ///
     /// If the image completely fills the y axis of the easel, this method
     /// does no drawing.
-    pub fn draw_top_border(&mut self) -> Result<(), Box<dyn Error>> {
+    pub fn draw_top_border(&mut self) {
         for iy in 0..self.offset.y {
-            self.easel.draw_line(
+            self.draw_line(
                 Coord::new(0, iy),
                 Coord::new(self.easel_size.x, iy),
-                &PaletteColor::White,
-            )?;
+                PaletteColor::White,
+            );
         }
-        self.current_color = self.easel.current_color;
-        Ok(())
     }
 
     /// Process the next pixel from a given image.
@@ -112,90 +403,267 @@ impl<'a> ImageDrawer<'a> {
     /// * `x`: The x coordinate of the pixel in image coordinates.
     /// * `y`: The x coordinate of the pixel in image coordinates.
     ///
-    pub fn handle_pixel(
-        &mut self,
-        rgba: &mut Rgba<u8>,
-        x: u32,
-        y: u32,
-    ) -> Result<(), Box<dyn Error>> {
+    pub fn handle_pixel(&mut self, rgba: &Rgba<u8>, x: u32, y: u32) {
         let mut in_coord = Coord::new(x as i32, y as i32);
         in_coord = in_coord + &self.offset;
         let closest_color = self.palette.colormap[self.palette.index_of(rgba)];
 
+        let current_color = match self.current_color {
+            Some(color) => color,
+            None => {
+                self.current_color = Some(closest_color);
+                return;
+            }
+        };
+
         // If we've hit the end of a row, draw the rest of the row before
         // moving on to the next row.
         if in_coord.y > self.current_pos.y {
-            self.easel.draw_line(
+            self.draw_line(
                 self.current_pos,
-                Coord::new(self.image_size.x + self.offset.x, self.current_pos.y),
-                &self.current_color,
-            )?;
+                Coord::new(self.image_size.x + self.offset.x - 1, self.current_pos.y),
+                current_color,
+            );
             self.current_pos = in_coord;
-            self.current_color = closest_color;
+            self.current_color = Some(closest_color);
+            return;
         }
 
         // If there's a color change, draw the line up to this pixel and stop.
-        if closest_color != self.current_color {
-            self.easel.draw_line(
+        if closest_color != current_color {
+            self.draw_line(
                 self.current_pos,
                 in_coord - &Coord::new(1, 0),
-                &self.current_color,
-            )?;
+                current_color,
+            );
             self.current_pos = in_coord;
-            self.current_color = closest_color;
+            self.current_color = Some(closest_color);
         }
-
-        Ok(())
     }
 
-    /// Draw the bottom white border and clean up the horizontal edges.
-    pub fn cleanup_image(&mut self) -> Result<(), Box<dyn Error>> {
+    /// Draw the last run of the image, then the white borders to either side of it and below
+    /// it, covering the easel right up to its edges.
+    pub fn cleanup_image(&mut self) {
+        if let Some(color) = self.current_color.take() {
+            self.draw_line(
+                self.current_pos,
+                Coord::new(self.image_size.x + self.offset.x - 1, self.current_pos.y),
+                color,
+            );
+        }
+        let image_rows = self.offset.y.max(0)..self.image_size.y + self.offset.y;
+
         // Clean up the left-most edge of the picture if one exists.
         let left_edge = self.offset.x - 1;
-        if left_edge > 0 {
-            self.easel.draw_line(
-                Coord::new(left_edge, 0),
-                Coord::new(left_edge, self.image_size.y),
-                &PaletteColor::White,
-            )?;
-            for ix in self.offset.y..self.image_size.y + self.offset.y {
-                self.easel.draw_line(
-                    Coord::new(0, ix),
-                    Coord::new(left_edge, ix),
-                    &PaletteColor::White,
-                )?;
+        if left_edge >= 0 {
+            for iy in image_rows.clone() {
+                self.draw_line(
+                    Coord::new(0, iy),
+                    Coord::new(left_edge, iy),
+                    PaletteColor::White,
+                );
             }
         }
 
         // Clean up the right-most edge of the picture if one exists.
-        let right_edge = self.image_size.x + self.offset.x + 1;
-        if right_edge < self.easel_size.x {
-            self.easel.draw_line(
-                Coord::new(right_edge, 0),
-                Coord::new(right_edge, self.image_size.y),
-                &PaletteColor::White,
-            )?;
-            for ix in self.offset.y..self.image_size.y + self.offset.y {
-                self.easel.draw_line(
-                    Coord::new(right_edge, ix),
-                    Coord::new(self.easel_size.x, ix),
-                    &PaletteColor::White,
-                )?;
+        let right_edge = self.image_size.x + self.offset.x;
+        if right_edge <= self.easel_size.x {
+            for iy in image_rows {
+                self.draw_line(
+                    Coord::new(right_edge, iy),
+                    Coord::new(self.easel_size.x, iy),
+                    PaletteColor::White,
+                );
             }
         }
 
         // Once we've hit the end of the picture, tidy up the bottom by drawing
         // white lines to fill in the entire canvas.
-        if self.current_pos.y < self.easel_size.y {
-            for iy in self.current_pos.y..self.easel_size.y {
-                self.easel.draw_line(
-                    Coord::new(0, iy),
-                    Coord::new(self.easel_size.x, iy),
-                    &PaletteColor::White,
-                )?;
+        for iy in self.image_size.y + self.offset.y..=self.easel_size.y {
+            self.draw_line(
+                Coord::new(0, iy),
+                Coord::new(self.easel_size.x, iy),
+                PaletteColor::White,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulated::tests::easel;
+
+    /// A dark blue image with a red stripe through it and a red last row.
+    fn image() -> RgbaImage {
+        let blue = PaletteColor::DarkBlue.get_rgba();
+        let red = PaletteColor::Red.get_rgba();
+        RgbaImage::from_fn(120, 180, |x, y| {
+            if (50..60).contains(&x) || y == 179 {
+                red
+            } else {
+                blue
             }
+        })
+    }
+
+    /// Draws the image on a simulated easel and checks every pixel of the easel: the image
+    /// should be centered on it with nothing but white around it.
+    fn check_drawing(options: &PlanOptions) {
+        check_drawing_of(&image(), options);
+    }
+
+    /// Draws the given portrait image on a simulated easel and checks every pixel of the easel
+    /// against the palette color closest to the image, returning the plan it was drawn with.
+    fn check_drawing_of(image: &RgbaImage, options: &PlanOptions) -> DrawPlan {
+        let mut easel = easel();
+        let plan = plan_image(image, &easel.easel_coords, options);
+        easel.execute_plan(&plan).unwrap();
+
+        // The simulated portrait easel is 201x301 pixels.
+        let (width, height) = (image.width() as i32, image.height() as i32);
+        let (offset_x, offset_y) = ((200 - width) / 2, (299 - height) / 2);
+        let palette = Palette::with_metric(options.metric);
+        let white = PaletteColor::White.get_rgba();
+        let canvas = easel.mouse.canvas();
+        for (x, y, pixel) in canvas.enumerate_pixels() {
+            let (ix, iy) = (x as i32 - offset_x, y as i32 - offset_y);
+            let expected = if (0..width).contains(&ix) && (0..height).contains(&iy) {
+                let index = palette.index_of(image.get_pixel(ix as u32, iy as u32));
+                palette.colormap[index].get_rgba()
+            } else {
+                white
+            };
+            assert_eq!(*pixel, expected, "pixel ({}, {})", x, y);
         }
+        plan
+    }
+
+    #[test]
+    fn the_border_around_the_image_is_white() {
+        check_drawing(&PlanOptions::default());
+    }
+
+    #[test]
+    fn the_border_is_white_after_filling_the_background() {
+        let image = image();
+        assert_eq!(
+            dominant_color(&image, ColorMetric::Rgb),
+            Some(PaletteColor::DarkBlue)
+        );
+        check_drawing(&PlanOptions {
+            fill_background: true,
+            ..PlanOptions::default()
+        });
+    }
+
+    #[test]
+    fn the_border_is_white_with_multiple_brushes() {
+        check_drawing(&PlanOptions {
+            fill_background: true,
+            multi_brush: true,
+            ..PlanOptions::default()
+        });
+    }
+
+    #[test]
+    fn flooding_white_leaves_no_borders_to_draw() {
+        let white = PaletteColor::White.get_rgba();
+        let blue = PaletteColor::DarkBlue.get_rgba();
+        let image = RgbaImage::from_fn(
+            120,
+            180,
+            |x, _| if (50..60).contains(&x) { blue } else { white },
+        );
+        let plan = check_drawing_of(
+            &image,
+            &PlanOptions {
+                fill_background: true,
+                ..PlanOptions::default()
+            },
+        );
+
+        // One stroke floods the easel, then one stroke per row draws the stripe.
+        assert_eq!(plan.num_strokes(), 1 + 180);
+        let whites = plan
+            .ops
+            .iter()
+            .filter(|op| **op == DrawOp::SetColor(PaletteColor::White))
+            .count();
+        assert_eq!(whites, 1);
+    }
+
+    #[test]
+    fn an_empty_image_has_no_dominant_color() {
+        assert_eq!(
+            dominant_color(&RgbaImage::new(0, 0), ColorMetric::Rgb),
+            None
+        );
+    }
+
+    #[test]
+    fn color_batches_pick_each_color_once_from_light_to_dark() {
+        let blue = PaletteColor::DarkBlue.get_rgba();
+        let red = PaletteColor::Red.get_rgba();
+        let yellow = PaletteColor::Yellow.get_rgba();
+        let image = RgbaImage::from_fn(120, 180, |x, y| match (x / 10 + y / 10) % 3 {
+            0 => blue,
+            1 => red,
+            _ => yellow,
+        });
+        let batched = check_drawing_of(
+            &image,
+            &PlanOptions {
+                order: DrawOrder::ColorBatched,
+                ..PlanOptions::default()
+            },
+        );
+
+        let colors: Vec<PaletteColor> = batched
+            .ops
+            .iter()
+            .filter_map(|op| match op {
+                DrawOp::SetColor(color) => Some(*color),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            colors,
+            vec![
+                PaletteColor::White,
+                PaletteColor::Yellow,
+                PaletteColor::Red,
+                PaletteColor::DarkBlue,
+            ]
+        );
+        assert!(colors
+            .windows(2)
+            .all(|pair| pair[0].lightness() > pair[1].lightness()));
+
+        let scanline = plan_image(&image, &easel().easel_coords, &PlanOptions::default());
+        assert_eq!(batched.num_strokes(), scanline.num_strokes());
+    }
+
+    #[test]
+    fn a_dry_run_paints_the_image_matched_to_the_palette() {
+        // A gradient through colors that aren't in the palette, scaled up to fill the easel.
+        let image = RgbaImage::from_fn(60, 90, |x, y| {
+            Rgba([(x * 4) as u8, (y * 2) as u8, 200 - (x + y) as u8, 0xff])
+        });
+        let path = std::env::temp_dir().join("passpartout_printer_dry_run.png");
+        image.save(&path).unwrap();
+        let coords = easel().easel_coords;
+        let image = open_image(
+            path.to_str().unwrap(),
+            &coords,
+            None,
+            true,
+            ColorMetric::Rgb,
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(image.dimensions(), (200, 300));
 
-        Ok(())
+        check_drawing_of(&image, &PlanOptions::default());
     }
 }
@@ -0,0 +1,374 @@
+use crate::coords::Coord;
+use crate::easel::NUM_BRUSH_STEPS;
+
+/// A stroke painting part of a layer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerStroke {
+    /// The brush size to paint the stroke with.
+    pub brush_size: i32,
+
+    /// The points the stroke is dragged through, relative to the upper-left corner of the layer.
+    pub points: Vec<Coord>,
+}
+
+/// A single color of an image, given as a mask of the pixels that should be painted in it.
+pub struct Layer {
+    mask: Vec<bool>,
+    width: usize,
+    height: usize,
+}
+
+impl Layer {
+    /// Creates a layer from a row-major mask of `width` by `height` pixels.
+    pub fn new(mask: Vec<bool>, width: usize, height: usize) -> Layer {
+        assert_eq!(mask.len(), width * height);
+        Layer {
+            mask,
+            width,
+            height,
+        }
+    }
+
+    /// Breaks the layer down into strokes, painting as much of it as possible with large
+    /// brushes before finishing off the edges with the smallest one.
+    ///
+    /// A large brush is only used where its whole width fits inside the layer, which is found
+    /// by eroding the layer by the radius of the brush. This way no stroke bleeds past the
+    /// edges of the layer into neighbouring colors. Strokes are returned from the largest brush
+    /// to the smallest.
+    ///
+    /// # Arguments
+    ///
+    /// * `brush_widths`: How many pixels wide each brush size paints, from 0 upwards.
+    ///
+    pub fn decompose(&self, brush_widths: &[u32]) -> Vec<LayerStroke> {
+        let distance = self.edge_distance();
+        let mut covered = vec![false; self.mask.len()];
+        let mut strokes = vec![];
+
+        for (brush_size, reach, radius) in brush_reaches(brush_widths).into_iter().rev() {
+            // Rows are spaced so that the squares covered by neighbouring strokes just touch.
+            let spacing = 2 * reach + 1;
+            let mut y = reach;
+            while y < self.height {
+                for (start, end) in self.runs(y, |ix| distance[ix] > radius) {
+                    let left = start.saturating_sub(reach);
+                    let right = (end + reach).min(self.width - 1);
+                    let top = y.saturating_sub(reach);
+                    let bottom = (y + reach).min(self.height - 1);
+
+                    // Skip strokes that wouldn't paint anything new.
+                    let paints_new = (top..=bottom).any(|iy| {
+                        (left..=right).any(|ix| {
+                            let index = iy * self.width + ix;
+                            self.mask[index] && !covered[index]
+                        })
+                    });
+                    if !paints_new {
+                        continue;
+                    }
+
+                    for iy in top..=bottom {
+                        for ix in left..=right {
+                            covered[iy * self.width + ix] = true;
+                        }
+                    }
+                    strokes.push(LayerStroke {
+                        brush_size,
+                        points: vec![
+                            Coord::new(start as i32, y as i32),
+                            Coord::new(end as i32, y as i32),
+                        ],
+                    });
+                }
+                y += spacing;
+            }
+        }
+
+        strokes.extend(self.trace_details(&covered));
+        strokes
+    }
+
+    /// Paints whatever the large brushes couldn't reach with the smallest brush.
+    ///
+    /// The leftovers are mostly thin slivers along the edges of the layer, so rather than
+    /// spending a stroke on every row of a sliver, runs on neighbouring rows that overlap are
+    /// chained into a single stroke that zig-zags down the sliver. Runs span any covered pixels
+    /// of the layer between uncovered ones, since painting them again does no harm.
+    fn trace_details(&self, covered: &[bool]) -> Vec<LayerStroke> {
+        let mut rows: Vec<Vec<(usize, usize)>> = (0..self.height)
+            .map(|y| {
+                self.runs(y, |ix| self.mask[ix])
+                    .into_iter()
+                    .filter(|&(start, end)| (start..=end).any(|x| !covered[y * self.width + x]))
+                    .collect()
+            })
+            .collect();
+
+        let mut strokes = vec![];
+        for y in 0..self.height {
+            while let Some((start, end)) = rows[y].pop() {
+                let mut points = vec![Coord::new(end as i32, y as i32)];
+                if start != end {
+                    points.push(Coord::new(start as i32, y as i32));
+                }
+                let (mut run_start, mut run_end) = (start, end);
+                let mut next_y = y + 1;
+                while next_y < self.height {
+                    let next = rows[next_y]
+                        .iter()
+                        .position(|&(s, e)| s <= run_end && e >= run_start);
+                    let (s, e) = match next {
+                        Some(i) => rows[next_y].remove(i),
+                        None => break,
+                    };
+
+                    // Step straight down within the overlap of the two runs so the link
+                    // between them stays inside the layer, then sweep the new run.
+                    let last = points.last().unwrap().x as usize;
+                    let link = last.clamp(run_start.max(s), run_end.min(e));
+                    let sweep = if link - s < e - link { [s, e] } else { [e, s] };
+                    for (x, row) in [
+                        (link, next_y - 1),
+                        (link, next_y),
+                        (sweep[0], next_y),
+                        (sweep[1], next_y),
+                    ] {
+                        let point = Coord::new(x as i32, row as i32);
+                        if *points.last().unwrap() != point {
+                            points.push(point);
+                        }
+                    }
+
+                    run_start = s;
+                    run_end = e;
+                    next_y += 1;
+                }
+                strokes.push(LayerStroke {
+                    brush_size: 0,
+                    points,
+                });
+            }
+        }
+        strokes
+    }
+
+    /// Finds the runs of consecutive pixels in a row that pass the given test.
+    fn runs<F: Fn(usize) -> bool>(&self, y: usize, test: F) -> Vec<(usize, usize)> {
+        let mut runs = vec![];
+        let mut start = None;
+        for x in 0..self.width {
+            let inside = test(y * self.width + x);
+            match (inside, start) {
+                (true, None) => start = Some(x),
+                (false, Some(s)) => {
+                    runs.push((s, x - 1));
+                    start = None;
+                }
+                _ => (),
+            }
+        }
+        if let Some(s) = start {
+            runs.push((s, self.width - 1));
+        }
+        runs
+    }
+
+    /// For every pixel, the distance to the nearest pixel outside the layer, measured as the
+    /// larger of the horizontal and vertical distances. Pixels outside the layer are 0 and the
+    /// edges of the image don't count as outside, since strokes running off the easel are
+    /// harmless.
+    fn edge_distance(&self) -> Vec<usize> {
+        let (width, height) = (self.width, self.height);
+        let far = width.max(height) + 1;
+        let mut distance: Vec<usize> = self
+            .mask
+            .iter()
+            .map(|&inside| if inside { far } else { 0 })
+            .collect();
+
+        // A two pass chamfer transform is exact for this distance.
+        for y in 0..height {
+            for x in 0..width {
+                let index = y * width + x;
+                if distance[index] == 0 {
+                    continue;
+                }
+                let mut best = distance[index];
+                if x > 0 {
+                    best = best.min(distance[index - 1] + 1);
+                }
+                if y > 0 {
+                    best = best.min(distance[index - width] + 1);
+                    if x > 0 {
+                        best = best.min(distance[index - width - 1] + 1);
+                    }
+                    if x + 1 < width {
+                        best = best.min(distance[index - width + 1] + 1);
+                    }
+                }
+                distance[index] = best;
+            }
+        }
+        for y in (0..height).rev() {
+            for x in (0..width).rev() {
+                let index = y * width + x;
+                if distance[index] == 0 {
+                    continue;
+                }
+                let mut best = distance[index];
+                if x + 1 < width {
+                    best = best.min(distance[index + 1] + 1);
+                }
+                if y + 1 < height {
+                    best = best.min(distance[index + width] + 1);
+                    if x + 1 < width {
+                        best = best.min(distance[index + width + 1] + 1);
+                    }
+                    if x > 0 {
+                        best = best.min(distance[index + width - 1] + 1);
+                    }
+                }
+                distance[index] = best;
+            }
+        }
+        distance
+    }
+}
+
+/// Works out which brush sizes are worth using for large areas. For each, returns the brush
+/// size, how far from the stroke it reliably covers, and its radius. Only the smallest brush
+/// size for each reach is kept, and brushes that can't reliably cover more than a single pixel
+/// are left out. The result is ordered from the smallest brush to the largest.
+fn brush_reaches(brush_widths: &[u32]) -> Vec<(i32, usize, usize)> {
+    let mut reaches: Vec<(i32, usize, usize)> = vec![];
+    for brush_size in 1..=NUM_BRUSH_STEPS {
+        let width = match brush_widths.get(brush_size as usize) {
+            Some(width) => *width as usize,
+            None => break,
+        };
+        // The brush is round, so the square it's sure to cover has a half-width of the radius
+        // divided by the square root of two.
+        let radius = width / 2;
+        let reach = (radius as f32 / f32::sqrt(2.0)) as usize;
+        let improves = reaches.last().map_or(true, |&(_, last, _)| reach > last);
+        if reach > 0 && improves {
+            reaches.push((brush_size, reach, radius));
+        }
+    }
+    reaches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::easel::BRUSH_WIDTHS;
+
+    /// A layer of the given size with only the pixels in the given rectangle set.
+    fn block(width: usize, height: usize, ul: (usize, usize), lr: (usize, usize)) -> Layer {
+        let mask = (0..width * height)
+            .map(|i| (ul.0..=lr.0).contains(&(i % width)) && (ul.1..=lr.1).contains(&(i / width)))
+            .collect();
+        Layer::new(mask, width, height)
+    }
+
+    /// Paints the strokes with round brushes of the given widths, the same way the easel does,
+    /// and returns which pixels of the layer end up painted.
+    fn paint(layer: &Layer, strokes: &[LayerStroke], brush_widths: &[u32]) -> Vec<bool> {
+        let mut painted = vec![false; layer.mask.len()];
+        for stroke in strokes {
+            let radius = brush_widths[stroke.brush_size as usize] as f32 / 2.0;
+            let reach = radius as i32;
+            let mut centers = vec![stroke.points[0]];
+            for pts in stroke.points.windows(2) {
+                let delta = &pts[1] - &pts[0];
+                let steps = delta.x.abs().max(delta.y.abs());
+                for step in 1..=steps {
+                    centers.push(Coord::new(
+                        pts[0].x + delta.x * step / steps,
+                        pts[0].y + delta.y * step / steps,
+                    ));
+                }
+            }
+            for center in centers {
+                for dy in -reach..=reach {
+                    for dx in -reach..=reach {
+                        let (x, y) = (center.x + dx, center.y + dy);
+                        if (dx * dx + dy * dy) as f32 > radius * radius
+                            || !(0..layer.width as i32).contains(&x)
+                            || !(0..layer.height as i32).contains(&y)
+                        {
+                            continue;
+                        }
+                        painted[y as usize * layer.width + x as usize] = true;
+                    }
+                }
+            }
+        }
+        painted
+    }
+
+    #[test]
+    fn edge_distance_counts_pixels_to_the_outside() {
+        let distance = block(7, 7, (1, 1), (5, 5)).edge_distance();
+        let row: Vec<usize> = distance[3 * 7..4 * 7].to_vec();
+        assert_eq!(row, vec![0, 1, 2, 3, 2, 1, 0]);
+        assert_eq!(distance[2 * 7 + 2], 2);
+        assert_eq!(distance[0], 0);
+    }
+
+    #[test]
+    fn edge_distance_ignores_the_edges_of_the_image() {
+        // The block runs off the left of the image, so only the gap on the right counts.
+        let distance = block(5, 1, (0, 0), (3, 0)).edge_distance();
+        assert_eq!(distance, vec![4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn brush_reaches_keep_the_smallest_brush_for_each_reach() {
+        let reaches = brush_reaches(&BRUSH_WIDTHS);
+        assert_eq!(reaches.first(), Some(&(1, 1, 2)));
+        assert_eq!(reaches.last(), Some(&(15, 16, 23)));
+        assert!(reaches.windows(2).all(|r| r[0].1 < r[1].1));
+    }
+
+    #[test]
+    fn brush_reaches_leave_out_brushes_too_small_to_help() {
+        assert!(brush_reaches(&[1, 1, 2, 3]).is_empty());
+        assert_eq!(brush_reaches(&[1, 3, 4]), vec![(2, 1, 2)]);
+    }
+
+    #[test]
+    fn a_thin_line_stays_at_the_smallest_brush() {
+        let layer = block(20, 5, (2, 2), (17, 2));
+        let strokes = layer.decompose(&BRUSH_WIDTHS);
+        assert_eq!(
+            strokes,
+            vec![LayerStroke {
+                brush_size: 0,
+                points: vec![Coord::new(17, 2), Coord::new(2, 2)],
+            }]
+        );
+    }
+
+    #[test]
+    fn a_solid_block_is_painted_with_a_large_brush_inside_its_edges() {
+        let layer = block(80, 80, (10, 10), (69, 69));
+        let strokes = layer.decompose(&BRUSH_WIDTHS);
+        assert!(strokes[0].brush_size >= 10);
+        assert!(strokes
+            .windows(2)
+            .all(|s| s[0].brush_size >= s[1].brush_size));
+
+        let painted = paint(&layer, &strokes, &BRUSH_WIDTHS);
+        for (index, (&inside, &painted)) in layer.mask.iter().zip(painted.iter()).enumerate() {
+            assert_eq!(
+                inside,
+                painted,
+                "pixel ({}, {})",
+                index % layer.width,
+                index / layer.width
+            );
+        }
+    }
+}
@@ -6,6 +6,24 @@
 //! arbitrary shapes instead of reading from images.
 //!
 
+/// Working out the configuration from a screenshot of the game.
+pub mod auto_config;
+
+/// The input backends an easel can use to move and click the mouse.
+pub mod backend;
+
+/// Measuring how the brushes actually paint in-game.
+pub mod calibrate;
+
+/// Screenshots of the game, used to see what has been painted on the easel.
+pub mod capture;
+
+/// Saving and restoring the progress of a drawing so it can be resumed.
+pub mod checkpoint;
+
+/// Reading, writing and migrating the configuration file.
+pub mod config;
+
 /// An interface to the colors that the game defines and provides a means of matching a given
 /// color to the closest color that Passpartout provides.
 pub mod colors;
@@ -13,11 +31,38 @@ pub mod colors;
 /// A very simple coordinate system that supports basic mathematical operations.
 pub mod coords;
 
+/// Dithering images down to the colors of the palette.
+pub mod dither;
+
 /// An abstration around the easel in-game and methods to work with and draw onto it.
 pub mod easel;
 
+/// The errors that can occur while configuring, planning or drawing.
+pub mod error;
+
+/// Tools for estimating how long a drawing will take before drawing it.
+pub mod estimate;
+
 /// A set of functions to pull in images and draw them onto an easel.
 pub mod image_drawer;
 
+/// Breaking each color of an image into areas that can be painted with large brushes.
+pub mod layers;
+
 /// Methods for generating the configuration files for the passpartout_printer application.
 pub mod manual_config;
+
+/// Plans describing a drawing as a list of operations, separate from carrying them out.
+pub mod plan;
+
+/// Named configurations, such as one for each screen resolution.
+pub mod profile;
+
+/// A simulated easel that paints into an image instead of the game.
+pub mod simulated;
+
+/// Checking what was drawn on the easel and planning repairs for anything that came out wrong.
+pub mod verify;
+
+/// Finding the game window, so coordinates can be kept relative to it.
+pub mod window;
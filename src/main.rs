@@ -3,128 +3,728 @@
 #[macro_use]
 extern crate clap;
 
-use clap::App;
+use clap::{App, ArgMatches};
 use device_query::{DeviceQuery, DeviceState, Keycode};
 use enigo::Enigo;
-use image::imageops::dither;
-use image::Pixel;
-use std::error::Error;
-use std::sync::mpsc;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
 use std::thread;
-use std::time::{Instant, Duration};
-use std::u64;
+use std::time::{Duration, Instant};
 
-mod colors;
-mod coords;
-mod easel;
-mod image_drawer;
-mod manual_config;
+use passpartout_printer::auto_config::auto_configure;
+use passpartout_printer::backend::PointerBackend;
+use passpartout_printer::calibrate::calibrate_brushes;
+use passpartout_printer::capture::{
+    capture_screen, screen_capture, FileCapture, ScreenCapture, WithCapture,
+};
+use passpartout_printer::checkpoint::Checkpointer;
+use passpartout_printer::colors::{self, ColorMetric, Palette};
+use passpartout_printer::config::{self, ConfigError};
+use passpartout_printer::coords::Coord;
+use passpartout_printer::dither::{DitherAlgorithm, DitherOptions};
+use passpartout_printer::easel::{Easel, EaselCoords};
+use passpartout_printer::error::PrinterError;
+use passpartout_printer::estimate::{Estimate, Estimator};
+use passpartout_printer::image_drawer::{open_image, plan_image, DrawOrder, PlanOptions};
+use passpartout_printer::manual_config;
+use passpartout_printer::plan::{DrawOp, DrawPlan};
+use passpartout_printer::profile;
+use passpartout_printer::simulated::SimulatedEasel;
+use passpartout_printer::verify::{expected_canvas, verify, Verification};
+use passpartout_printer::window::find_window_by_title;
 
-use colors::Palette;
-use easel::Easel;
-use image_drawer::size_to_easel;
-use image_drawer::ImageDrawer;
-
-fn app() -> Result<(), Box<dyn Error>> {
+fn app() -> Result<(), PrinterError> {
+    let drawing_args = "-w, --mouse-wait=[WAIT] 'Specify the time to wait between mouse actions'
+            --dry-run 'Simulates the drawing without touching the mouse.'
+            --estimate 'Estimates the draw time without drawing anything.'
+            --preview=[PREVIEW] 'With --dry-run, write the simulated easel to this PNG'
+            --resume 'Resumes an interrupted drawing from its checkpoint.'
+            --checkpoint=[CHECKPOINT] 'File to save drawing progress to (default: checkpoint.json)'
+            --verify 'Captures the easel after drawing and redraws anything that came out wrong'
+            --clip 'Draws only the parts of strokes on the easel instead of stopping at the first point off it'
+            --capture-file=[SCREENSHOT] 'Uses this screenshot instead of capturing the screen'";
+    let image_args =
+        "--enable-dither 'Enables dithering to reduce color banding but increase draw time'
+            --dither=[ALGO] 'Dithers with floyd-steinberg, atkinson, jarvis, sierra, bayer or blue-noise'
+            --serpentine 'With error diffusion dithering, scans every other row right to left'
+            --min-run=[N] 'Dithers without leaving runs of a color shorter than N pixels, implies --enable-dither'
+            --no-scale 'Disable scaling of the input image.'
+            --order=[ORDER] 'Order to draw in: scanline (default) or color, which batches colors'
+            --fill-background 'Paints the most common color over the whole easel first'
+            --multi-brush 'Paints large areas with large brushes, implies --order color'";
+    let metric_arg =
+        "--color-metric=[METRIC] 'How to match colors: rgb (default), redmean, cie76 or ciede2000'";
+    let palette_arg =
+        "--palette=[PALETTE] 'Recolors or moves the game's colors with this palette file, for the whole run'";
     let matches = App::new("Passpartout Printer")
         .version(crate_version!())
+        .args_from_usage(palette_arg)
+        .args_from_usage(metric_arg)
+        .args_from_usage(drawing_args)
+        .args_from_usage(image_args)
         .args_from_usage(
-            "-w, --mouse-wait=[WAIT] 'Specify the time to wait between mouse actions'
-            --configure 'Configures the application with coordinates in-game.'
-            --enable-dither 'Enables dithering to reduce color banding but increase draw time'
-            --no-scale 'Disable scaling of the input image.'
+            "--configure 'Configures the application with coordinates in-game.'
+            --calibrate-brushes 'Measures how wide each brush size paints and saves it to the configuration'
+            --auto-configure 'Configures the application from a screenshot of the easel.'
+            --check-config 'Checks the configuration for mistakes.'
+            --profile=[NAME] 'Uses the named configuration profile instead of coords.json'
+            --config-dir=[DIR] 'Where configuration profiles are stored'
+            --list-profiles 'Lists the saved configuration profiles.'
+            --save-palette=[FILE] 'Writes the palette in use to a file, to tune and use with --palette'
+            --derive-profile=[NAME] 'Saves the configuration scaled to --resolution as a new profile'
+            --resolution=[WxH] 'With --derive-profile, the resolution to scale to'
+            --from-resolution=[WxH] 'With --derive-profile, the resolution the configuration was made at'
+            --window=[TITLE] 'With --configure or --auto-configure, saves positions relative to this window'
+            --hover 'With --check-config, points the mouse at each element to confirm it'
+            --templates=[DIR] 'With --auto-configure, where the button images are (default: templates)'
             -i, --image=[IMAGE] 'Input image to use'",
         )
+        .subcommand(
+            App::new("plan")
+                .about("Plans out the drawing of an image and saves it to a file.")
+                .args_from_usage(palette_arg)
+                .args_from_usage(metric_arg)
+                .args_from_usage(image_args)
+                .args_from_usage(
+                    "-i, --image=<IMAGE> 'Input image to use'
+                    -o, --output=<OUTPUT> 'File to save the plan to'",
+                ),
+        )
+        .subcommand(
+            App::new("run")
+                .about("Draws a plan previously saved with the plan command.")
+                .args_from_usage(palette_arg)
+                .args_from_usage(metric_arg)
+                .args_from_usage(drawing_args)
+                .args_from_usage("<PLAN> 'The plan file to draw'"),
+        )
         .get_matches();
 
+    // The palette has to be settled before any color is used.
+    let palette_path = match matches.subcommand() {
+        Some((_, sub_matches)) => sub_matches
+            .value_of("palette")
+            .or_else(|| matches.value_of("palette")),
+        None => matches.value_of("palette"),
+    };
+    if let Some(path) = palette_path {
+        colors::use_palette(config::load_palette(path)?).map_err(ConfigError::Invalid)?;
+    }
+
+    if let Some(path) = matches.value_of("save-palette") {
+        config::save_palette(colors::palette_in_use(), path)?;
+        println!("Saved the palette to {}.", path);
+        return Ok(());
+    }
+
+    let config_dir = matches.value_of("config-dir");
+    let config_path = profile::config_path(matches.value_of("profile"), config_dir);
+    let config_path = config_path.to_string_lossy().into_owned();
+
+    if matches.occurrences_of("list-profiles") > 0 {
+        for name in profile::list_profiles(config_dir)? {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
     if matches.occurrences_of("configure") > 0 {
-        return manual_config::create_config("coords.json");
+        manual_config::create_config(&config_path)?;
+        if let Some(title) = matches.value_of("window") {
+            let easel_coords = EaselCoords::new(config_path.clone())?;
+            relative_to_window(&easel_coords, title)?.save(&config_path)?;
+        }
+        return Ok(());
     }
 
+    if matches.occurrences_of("auto-configure") > 0 {
+        let screenshot = match matches.value_of("capture-file") {
+            Some(path) => image::open(path)?.into_rgba8(),
+            None => capture_screen().map_err(PrinterError::Backend)?,
+        };
+        let templates = matches.value_of("templates").unwrap_or("templates");
+        let mut easel_coords = auto_configure(&screenshot, templates)
+            .map_err(|e| ConfigError::Invalid(e.to_string()))?;
+        if let Some(title) = matches.value_of("window") {
+            easel_coords = relative_to_window(&easel_coords, title)?;
+        }
+        easel_coords.save(&config_path)?;
+        println!("Saved the configuration to {}.", config_path);
+        return Ok(());
+    }
+
+    let easel_coords = EaselCoords::new(config_path.clone())?;
+
+    if let Some(name) = matches.value_of("derive-profile") {
+        return derive_profile(&easel_coords, name, config_dir, &matches);
+    }
+
+    if matches.occurrences_of("check-config") > 0 {
+        return check_config(&easel_coords, &config_path, &matches);
+    }
+
+    if matches.occurrences_of("calibrate-brushes") > 0 {
+        return calibrate(easel_coords, &config_path, &matches);
+    }
+
+    match matches.subcommand() {
+        Some(("plan", plan_matches)) => {
+            let plan = plan_from_matches(plan_matches, &easel_coords)?;
+            let output = plan_matches.value_of("output").unwrap();
+            plan.save(output)?;
+            println!(
+                "Saved a plan with {} strokes to {}.",
+                plan.num_strokes(),
+                output
+            );
+            Ok(())
+        }
+        Some(("run", run_matches)) => {
+            let plan_path = run_matches.value_of("PLAN").unwrap();
+            println!("Printing to Passpartout with the following settings:");
+            println!("-- plan: {}", plan_path);
+            let plan = DrawPlan::load(plan_path)?;
+            draw(&plan, plan_path, easel_coords, run_matches)
+        }
+        _ => {
+            let plan = plan_from_matches(&matches, &easel_coords)?;
+            let image_path = matches.value_of("image").unwrap();
+            draw(&plan, image_path, easel_coords, &matches)
+        }
+    }
+}
+
+/// Plans out the drawing of the image given on the command line.
+fn plan_from_matches(
+    matches: &ArgMatches,
+    easel_coords: &EaselCoords,
+) -> Result<DrawPlan, PrinterError> {
+    let image_path: String = matches
+        .value_of("image")
+        .ok_or_else(|| {
+            PrinterError::InvalidPlan("please give the image to draw with --image".to_string())
+        })?
+        .to_string();
+    let enable_dither: bool = !matches!(matches.occurrences_of("enable-dither"), 0);
+    let serpentine: bool = !matches!(matches.occurrences_of("serpentine"), 0);
+    let min_run = match matches.value_of("min-run") {
+        Some(min_run) => Some(
+            min_run
+                .parse::<u32>()
+                .map_err(|e| PrinterError::InvalidPlan(format!("--min-run: {}", e)))?,
+        ),
+        None => None,
+    };
+    let dither = match matches.value_of("dither") {
+        Some(algorithm) => Some(DitherOptions {
+            algorithm: algorithm
+                .parse::<DitherAlgorithm>()
+                .map_err(PrinterError::InvalidPlan)?,
+            serpentine,
+            min_run: min_run.unwrap_or(1),
+        }),
+        None if enable_dither || min_run.is_some() => Some(DitherOptions {
+            serpentine,
+            min_run: min_run.unwrap_or(1),
+            ..DitherOptions::default()
+        }),
+        None => None,
+    };
+    let enable_scale: bool = matches!(matches.occurrences_of("no-scale"), 0);
+    let order = match matches.value_of("order") {
+        Some(order) => order
+            .parse::<DrawOrder>()
+            .map_err(PrinterError::InvalidPlan)?,
+        None => DrawOrder::Scanline,
+    };
+
+    println!("Printing to Passpartout with the following settings:");
+    println!("-- image: {}", image_path);
+    match dither {
+        Some(options) => {
+            println!("-- dithering: {:?}", options.algorithm);
+            println!("-- serpentine: {}", options.serpentine);
+            println!("-- minimum run: {}", options.min_run.max(1));
+        }
+        None => println!("-- dithering: none"),
+    }
+    println!("-- image scaling: {}", enable_scale);
+    let fill_background: bool = !matches!(matches.occurrences_of("fill-background"), 0);
+
+    println!("-- draw order: {:?}", order);
+    let multi_brush: bool = !matches!(matches.occurrences_of("multi-brush"), 0);
+    let metric = color_metric(matches)?;
+    println!("-- fill background: {}", fill_background);
+    println!("-- multiple brushes: {}", multi_brush);
+    println!("-- color metric: {:?}", metric);
+
+    let options = PlanOptions {
+        order,
+        fill_background,
+        multi_brush,
+        metric,
+    };
+    let image = open_image(&image_path, easel_coords, dither, enable_scale, metric)?;
+    Ok(plan_image(&image, easel_coords, &options))
+}
+
+/// The color metric given on the command line.
+fn color_metric(matches: &ArgMatches) -> Result<ColorMetric, PrinterError> {
+    match matches.value_of("color-metric") {
+        Some(metric) => metric
+            .parse::<ColorMetric>()
+            .map_err(PrinterError::InvalidPlan),
+        None => Ok(ColorMetric::Rgb),
+    }
+}
+
+/// Draws a plan onto the easel, or simulates drawing it if a dry run or estimate was asked for.
+/// `source` is the image or plan file the plan came from.
+fn draw(
+    plan: &DrawPlan,
+    source: &str,
+    easel_coords: EaselCoords,
+    matches: &ArgMatches,
+) -> Result<(), PrinterError> {
+    let mut mouse_wait: u64 = value_t!(matches, "mouse-wait", u64).unwrap_or(7);
+    let dry_run: bool = !matches!(matches.occurrences_of("dry-run"), 0);
+    let estimate: bool = !matches!(matches.occurrences_of("estimate"), 0);
+    let resume: bool = !matches!(matches.occurrences_of("resume"), 0);
+    let checkpoint_path = matches.value_of("checkpoint").unwrap_or("checkpoint.json");
+    let verify_drawing: bool = !matches!(matches.occurrences_of("verify"), 0);
+    let capture_file = matches.value_of("capture-file");
+    let clip: bool = !matches!(matches.occurrences_of("clip"), 0);
+    let palette = Palette::with_metric(color_metric(matches)?);
+
+    let mut checkpointer = Checkpointer::new(checkpoint_path, source, plan, Duration::from_secs(5));
+    let checkpoint = if resume {
+        let checkpoint = checkpointer.load()?;
+        // A resumed drawing keeps the wait it was started with unless it's given again.
+        if matches.occurrences_of("mouse-wait") == 0 {
+            mouse_wait = checkpoint.mouse_wait;
+        } else if mouse_wait != checkpoint.mouse_wait {
+            println!(
+                "The drawing was started with a mouse wait of {}, resuming with {}.",
+                checkpoint.mouse_wait, mouse_wait
+            );
+        }
+        Some(checkpoint)
+    } else {
+        None
+    };
+
+    println!("-- mouse wait: {}", mouse_wait);
+    println!("-- dry run: {}", dry_run);
+    println!("-- checkpoint: {}", checkpoint_path);
+    println!("-- verify: {}", verify_drawing);
+    println!();
+    if let Some(checkpoint) = &checkpoint {
+        println!(
+            "Resuming from operation {} of {}.",
+            checkpoint.next_op,
+            plan.ops.len()
+        );
+    }
+
+    let target = if verify_drawing {
+        Some(expected_canvas(plan, &easel_coords)?)
+    } else {
+        None
+    };
+    let start_op = checkpoint.as_ref().map_or(0, |c| c.next_op);
+
+    let wait_time = Duration::from_millis(mouse_wait);
+    if estimate {
+        let estimator = Estimator::new(&easel_coords);
+        let mut easel = Easel::with_coords(easel_coords, estimator, wait_time);
+        easel.clip_strokes = clip;
+        if let Some(checkpoint) = checkpoint {
+            easel.execute(&DrawOp::SetOrientation(checkpoint.orientation))?;
+            checkpoint.restore(&mut easel);
+        }
+        execute_plan(&mut easel, plan, start_op, None, None)?;
+        print_estimate(&easel.mouse.estimate);
+        return Ok(());
+    }
+
+    if dry_run {
+        let simulated = SimulatedEasel::new(&easel_coords);
+        let mut easel = Easel::with_coords(easel_coords, simulated, wait_time);
+        easel.clip_strokes = clip;
+        if let Some(checkpoint) = checkpoint {
+            easel.execute(&DrawOp::SetOrientation(checkpoint.orientation))?;
+            checkpoint.restore(&mut easel);
+        }
+        let start = Instant::now();
+        execute_plan(&mut easel, plan, start_op, None, None)?;
+        if let Some(target) = &target {
+            let verification = match capture_file {
+                Some(path) => verify(
+                    &mut FileCapture::open(path)?,
+                    &easel.easel_coords,
+                    easel.orientation,
+                    target,
+                    &palette,
+                )?,
+                None => verify(
+                    &mut easel.mouse,
+                    &easel.easel_coords,
+                    easel.orientation,
+                    target,
+                    &palette,
+                )?,
+            };
+            repair(&mut easel, &verification, None)?;
+        }
+
+        let simulated = easel.mouse;
+        println!("Dry run complete in {:.1?}.", start.elapsed());
+        print_estimate(&simulated.estimate());
+        if let Some(preview) = matches.value_of("preview") {
+            simulated.canvas().save(preview)?;
+            println!("-- preview written to {}", preview);
+        }
+        return Ok(());
+    }
+
+    println!("Press Left Control + Space to pause drawing.");
+    println!("Press Left Control + Escape to stop drawing.");
+    println!();
+
     let (tx, rx) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_key = stop.clone();
 
-    // A simple event loop to search for the escape key to pause drawing.
+    // A simple event loop to search for the keys to pause or stop drawing. Stopping sets the
+    // easel's stop flag straight away so even a long stroke is cut short.
     thread::spawn(move || {
         let device_state = DeviceState::new();
         let mut prev = false;
         loop {
             let key_pressed = device_state.get_keys();
+            if key_pressed.contains(&Keycode::Escape) && key_pressed.contains(&Keycode::LControl) {
+                stop_key.store(true, Ordering::SeqCst);
+                let _ = tx.send(Control::Abort);
+                return;
+            }
             if key_pressed.contains(&Keycode::Space) && key_pressed.contains(&Keycode::LControl) {
                 prev = true;
             } else if prev {
                 prev = false;
-                tx.send(()).unwrap();
+                if tx.send(Control::Pause).is_err() {
+                    return;
+                }
             }
             thread::sleep(Duration::from_millis(100));
         }
     });
 
-    let easel_config = String::from("coords.json");
-    let image_path: String = matches
-        .value_of("image")
-        .expect("Please enter a path to the image to draw.")
-        .to_string();
+    let enigo = Enigo::new();
+    let mut easel = screen_easel(easel_coords, enigo, wait_time)?;
+    easel.clip_strokes = clip;
+    easel.stop = stop;
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.restore(&mut easel);
+    }
+    execute_plan(
+        &mut easel,
+        plan,
+        start_op,
+        Some(&rx),
+        Some(&mut checkpointer),
+    )?;
+    if let Some(target) = &target {
+        let mut capture: Box<dyn ScreenCapture> = match capture_file {
+            Some(path) => Box::new(FileCapture::open(path)?),
+            None => screen_capture().map_err(PrinterError::Backend)?,
+        };
+        let screen_coords = easel.screen_coords();
+        let verification = verify(
+            &mut capture,
+            &screen_coords,
+            easel.orientation,
+            target,
+            &palette,
+        )?;
+        repair(&mut easel, &verification, Some(&rx))?;
+    }
+    checkpointer.finish()
+}
+
+/// Reports how much of the drawing came out wrong and redraws it.
+fn repair<B: PointerBackend>(
+    easel: &mut Easel<B>,
+    verification: &Verification,
+    pause: Option<&Receiver<Control>>,
+) -> Result<(), PrinterError> {
+    println!(
+        "-- wrong pixels: {} of {}",
+        verification.wrong_pixels, verification.total_pixels
+    );
+    if verification.repair.ops.is_empty() {
+        return Ok(());
+    }
+    println!(
+        "Repairing the drawing with {} strokes.",
+        verification.repair.num_strokes()
+    );
+    execute_plan(easel, &verification.repair, 0, pause, None)
+}
+
+/// Saves a copy of the configuration scaled to a new screen resolution as the named profile.
+fn derive_profile(
+    easel_coords: &EaselCoords,
+    name: &str,
+    config_dir: Option<&str>,
+    matches: &ArgMatches,
+) -> Result<(), PrinterError> {
+    let to = match matches.value_of("resolution") {
+        Some(resolution) => profile::parse_resolution(resolution)?,
+        None => Err(ConfigError::Invalid(
+            "--derive-profile needs the --resolution to scale to".to_string(),
+        ))?,
+    };
+    let from = match matches.value_of("from-resolution") {
+        Some(resolution) => profile::parse_resolution(resolution)?,
+        None => easel_coords.screen_size.ok_or_else(|| {
+            ConfigError::Invalid(
+                "the configuration doesn't say what resolution it was made at, pass it with \
+                 --from-resolution"
+                    .to_string(),
+            )
+        })?,
+    };
+    if from.0 == 0 || from.1 == 0 {
+        Err(ConfigError::Invalid(format!(
+            "the configuration was made at an invalid resolution of {}x{}, pass the right one \
+             with --from-resolution",
+            from.0, from.1
+        )))?
+    }
+    if let Some(title) = &easel_coords.window {
+        println!(
+            "The coordinates are relative to the {} window, so they only scale correctly if the \
+             window fills the screen at both resolutions.",
+            title
+        );
+    }
+
+    let path = profile::config_path(Some(name), config_dir);
+    let path = path.to_string_lossy();
+    easel_coords.scaled(from, to).save(&path)?;
+    println!(
+        "Saved the configuration scaled from {}x{} to {}x{} to {}.",
+        from.0, from.1, to.0, to.1, path
+    );
+    Ok(())
+}
+
+/// Reports any mistakes in the configuration and, if asked, points the mouse at each element so
+/// its position can be checked by eye.
+fn check_config(
+    easel_coords: &EaselCoords,
+    config_path: &str,
+    matches: &ArgMatches,
+) -> Result<(), PrinterError> {
+    let problems = easel_coords.check();
+    for problem in problems.iter() {
+        println!("-- {}", problem);
+    }
+
+    if matches.occurrences_of("hover") > 0 {
+        println!("Pointing at each element of the easel in turn.");
+        let screen_coords = match &easel_coords.window {
+            Some(title) => easel_coords.translated(window_origin(title)?),
+            None => easel_coords.clone(),
+        };
+        let mut enigo = Enigo::new();
+        manual_config::hover_elements(&screen_coords, &mut enigo, Duration::from_secs(1));
+    }
+
+    if problems.is_empty() {
+        println!("No problems found in {}.", config_path);
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid(format!(
+            "found {} problems in {}",
+            problems.len(),
+            config_path
+        )))?
+    }
+}
+
+/// Measures the width of every brush size and saves them to the configuration. A dry run
+/// calibrates against the simulated easel and only prints the widths.
+fn calibrate(
+    easel_coords: EaselCoords,
+    config_path: &str,
+    matches: &ArgMatches,
+) -> Result<(), PrinterError> {
     let mouse_wait: u64 = value_t!(matches, "mouse-wait", u64).unwrap_or(7);
-    let enable_dither: bool = !matches!(matches.occurrences_of("enable-dither"), 0);
-    let enable_scale: bool = matches!(matches.occurrences_of("no-scale"), 0);
+    let dry_run: bool = !matches!(matches.occurrences_of("dry-run"), 0);
+    let wait_time = Duration::from_millis(mouse_wait);
 
-    println!("Printing to Passpartout with the following settings:");
-    println!("-- image: {}", image_path);
+    println!("Calibrating brush sizes with the following settings:");
     println!("-- mouse wait: {}", mouse_wait);
-    println!("-- dithering: {}", enable_dither);
-    println!("-- image scaling: {}", enable_scale);
-    println!();
-    println!("Press Left Control + Space to pause drawing.");
+    println!("-- dry run: {}", dry_run);
     println!();
 
-    let wait_time = Duration::from_millis(mouse_wait);
-    let enigo = Enigo::new();
-    let mut easel = Easel::new(easel_config, enigo, wait_time)?;
-    let mut image = if enable_scale {
-        size_to_easel(&image::open(image_path)?, &easel).into_rgba8()
-    } else {
-        image::open(image_path)?.into_rgba8()
+    if dry_run {
+        let simulated = SimulatedEasel::new(&easel_coords);
+        let mut easel = Easel::with_coords(easel_coords, simulated, wait_time);
+        calibrate_brushes(&mut easel).map_err(PrinterError::Backend)?;
+        return Ok(());
+    }
+
+    println!("Make sure the easel is blank and in portrait before starting.");
+    let backend = WithCapture {
+        backend: Enigo::new(),
+        capture: screen_capture().map_err(PrinterError::Backend)?,
     };
-    let palette = Palette::new();
-    if enable_dither {
-        dither(&mut image, &palette);
+    let mut easel = screen_easel(easel_coords, backend, wait_time)?;
+    let widths = calibrate_brushes(&mut easel).map_err(PrinterError::Backend)?;
+
+    let mut easel_coords = easel.easel_coords;
+    easel_coords.brush_widths = widths;
+    easel_coords.save(config_path)?;
+    println!("Saved the brush widths to {}.", config_path);
+    Ok(())
+}
+
+/// Creates an easel that draws on the screen, following the game window if the configuration
+/// is relative to one.
+fn screen_easel<B: PointerBackend>(
+    easel_coords: EaselCoords,
+    mouse: B,
+    mouse_wait: Duration,
+) -> Result<Easel<B>, PrinterError> {
+    match easel_coords.window.clone() {
+        Some(title) => {
+            let window = find_window_by_title(&title).map_err(PrinterError::Backend)?;
+            Easel::with_window(easel_coords, mouse, mouse_wait, window)
+        }
+        None => Ok(Easel::with_coords(easel_coords, mouse, mouse_wait)),
     }
+}
+
+/// Makes a configuration taken in screen coordinates relative to the window with the given
+/// title, wherever it is now.
+fn relative_to_window(
+    easel_coords: &EaselCoords,
+    title: &str,
+) -> Result<EaselCoords, PrinterError> {
+    let origin = window_origin(title)?;
+    println!("Found the window {} at {:?}.", title, origin);
+    Ok(easel_coords.relative_to(title, origin))
+}
+
+/// Finds where the window with the given title is on the screen.
+fn window_origin(title: &str) -> Result<Coord, PrinterError> {
+    find_window_by_title(title)
+        .and_then(|mut window| window.origin())
+        .map_err(PrinterError::Backend)
+}
 
-    let (size_x, size_y) = image.dimensions();
-    let mut image_drawer = ImageDrawer::new(&mut easel, size_x, size_y);
-    image_drawer.draw_top_border()?;
+/// What the user asked for from the keyboard while drawing.
+enum Control {
+    /// Pause the drawing, or resume it if it's paused.
+    Pause,
+
+    /// Stop the drawing altogether.
+    Abort,
+}
 
+/// Carries out a plan on the easel, starting from the operation at `start_op`. If a control
+/// channel is given, drawing pauses and resumes whenever a pause arrives on it and stops when
+/// an abort does. If a checkpointer is given, progress is saved as the plan is drawn and when
+/// an operation fails or the drawing is stopped.
+fn execute_plan<B: PointerBackend>(
+    easel: &mut Easel<B>,
+    plan: &DrawPlan,
+    start_op: usize,
+    pause: Option<&Receiver<Control>>,
+    mut checkpointer: Option<&mut Checkpointer>,
+) -> Result<(), PrinterError> {
     let mut paused = false;
     let mut check_pause = Instant::now();
-    for (x, y, pixel) in image.enumerate_pixels_mut() {
+    for (index, op) in plan.ops.iter().enumerate().skip(start_op) {
         // Loop to handle pausing drawing so the user can actually get control
         // of their machine again.
-        if check_pause.elapsed() >= Duration::from_millis(100) {
-            loop {
-                if let Ok(()) = rx.try_recv() {
-                    paused = !paused;
-                    if paused {
-                        println!("Pausing printing.");
-                    } else {
-                        println!("Resuming printing.");
+        let mut result = Ok(());
+        if let Some(rx) = pause {
+            if check_pause.elapsed() >= Duration::from_millis(100) {
+                loop {
+                    match rx.try_recv() {
+                        Ok(Control::Pause) => {
+                            paused = !paused;
+                            if paused {
+                                println!("Pausing printing.");
+                            } else {
+                                println!("Resuming printing.");
+                            }
+                        }
+                        Ok(Control::Abort) => {
+                            result = Err(PrinterError::Aborted);
+                            break;
+                        }
+                        Err(_) => (),
+                    }
+                    if !paused {
+                        break;
                     }
                 }
-                if !paused {
-                    break;
-                }
+                check_pause = Instant::now();
             }
-            check_pause = Instant::now();
         }
 
-        let mut rgba = pixel.to_rgba();
-        image_drawer.handle_pixel(&mut rgba, x, y)?;
-    }
+        if let Err(e) = result {
+            if let Some(checkpointer) = checkpointer.as_mut() {
+                checkpointer.save(index, easel)?;
+            }
+            return Err(e);
+        }
 
-    image_drawer.cleanup_image()?;
+        // The checkpointer saves the drawing's progress if the operation fails or panics.
+        match checkpointer.as_mut() {
+            Some(checkpointer) => checkpointer.execute(index, op, easel)?,
+            None => easel.execute(op)?,
+        }
+    }
 
     Ok(())
 }
 
+/// Prints a summary of the mouse operations a drawing needs.
+fn print_estimate(estimate: &Estimate) {
+    println!("-- strokes: {}", estimate.strokes);
+    println!("-- color changes: {}", estimate.color_changes);
+    println!("-- brush size clicks: {}", estimate.brush_changes);
+    println!("-- mouse operations: {}", estimate.mouse_operations());
+    println!(
+        "-- estimated draw time: {}",
+        format_duration(estimate.duration)
+    );
+}
+
+/// Formats a duration as hours, minutes and seconds.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!(
+        "{}h {:02}m {:02}s",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
 fn main() {
-    app().unwrap();
+    if let Err(e) = app() {
+        eprintln!("Error: {}", e);
+        if let PrinterError::Aborted = e {
+            eprintln!("Continue where the drawing left off with --resume.");
+        }
+        process::exit(e.exit_code());
+    }
 }
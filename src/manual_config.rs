@@ -1,13 +1,14 @@
+use crate::backend::PointerBackend;
 use crate::coords::Coord;
-use crate::easel::EaselCoords;
+use crate::easel::{EaselCoords, BRUSH_WIDTHS};
+use crate::error::PrinterError;
 use device_query::{DeviceQuery, DeviceState};
-use std::error::Error;
 use std::thread;
 use std::time::Duration;
 
 /// A utility function for gathering locations of items in-game and generating a configuration
 /// file to be used to draw images.
-pub fn create_config(path: &str) -> Result<(), Box<dyn Error>> {
+pub fn create_config(path: &str) -> Result<(), PrinterError> {
     println!("This will walk you through creation of a configuration file.");
     println!("First, let's gather the portrait coordinates.");
     println!("This will reset the cursor after each click.");
@@ -26,7 +27,7 @@ pub fn create_config(path: &str) -> Result<(), Box<dyn Error>> {
     println!("Please click on the upper left corner of the easel.");
     let landscape_ul = get_pos();
 
-    println!("Please elick on the lower right corner of the easel.");
+    println!("Please click on the lower right corner of the easel.");
     let landscape_lr = get_pos();
 
     println!("Please click on the button to decrease brush size.");
@@ -70,11 +71,54 @@ pub fn create_config(path: &str) -> Result<(), Box<dyn Error>> {
         color_start,
         color_row_step,
         color_col_step,
+        brush_widths: BRUSH_WIDTHS.to_vec(),
+        screen_size: None,
+        window: None,
     };
 
     easel_coords.save(path)
 }
 
+/// Moves the mouse over each element of the easel in turn, naming it in the console, so its
+/// position can be confirmed by eye. Nothing is clicked.
+pub fn hover_elements<B: PointerBackend>(
+    easel_coords: &EaselCoords,
+    mouse: &mut B,
+    wait: Duration,
+) {
+    let corners = [
+        (
+            "upper left corner of the portrait easel",
+            easel_coords.portrait_bounds.0,
+        ),
+        (
+            "lower right corner of the portrait easel",
+            easel_coords.portrait_bounds.1,
+        ),
+        (
+            "upper left corner of the landscape easel",
+            easel_coords.landscape_bounds.0,
+        ),
+        (
+            "lower right corner of the landscape easel",
+            easel_coords.landscape_bounds.1,
+        ),
+    ];
+    let corners = corners
+        .iter()
+        .map(|(name, coord)| (name.to_string(), *coord));
+    let elements = easel_coords
+        .elements()
+        .into_iter()
+        .map(|(name, coord, _)| (name, coord));
+
+    for (name, coord) in corners.chain(elements) {
+        println!("-- {} at {:?}", name, coord);
+        mouse.move_to(coord.x, coord.y);
+        mouse.sleep(wait);
+    }
+}
+
 fn get_pos() -> Coord {
     let mut mouse_pos = (0, 0);
     let device_query = DeviceState::new();
@@ -0,0 +1,215 @@
+use crate::colors::PaletteColor;
+use crate::coords::Coord;
+use crate::easel::{Orientation, Tool};
+use crate::error::PrinterError;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Write};
+
+/// A single step of a drawing. Coordinates are relative to the upper-left corner of the easel,
+/// the same as the coordinates given to `Easel::draw_shape`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DrawOp {
+    /// Switch to the given tool.
+    SelectTool(Tool),
+
+    /// Change the brush to the given size, from 0 to `NUM_BRUSH_STEPS`.
+    SetBrush(i32),
+
+    /// Pick up the given color from the palette.
+    SetColor(PaletteColor),
+
+    /// Drag the mouse through the given points as one continuous stroke.
+    Stroke(Vec<Coord>),
+
+    /// Turn the easel to the given orientation.
+    SetOrientation(Orientation),
+}
+
+/// An ordered list of operations that together make up a drawing. Plans are produced by a
+/// planner, such as `ImageDrawer` or `plan_shape`, and carried out by `Easel::execute_plan`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct DrawPlan {
+    pub ops: Vec<DrawOp>,
+}
+
+impl DrawPlan {
+    pub fn new() -> DrawPlan {
+        DrawPlan { ops: vec![] }
+    }
+
+    /// Loads a plan previously saved with `DrawPlan::save`.
+    pub fn load(path: &str) -> Result<DrawPlan, PrinterError> {
+        let mut file = File::open(path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        serde_json::from_str(&contents)
+            .map_err(|e| PrinterError::InvalidPlan(format!("{}: {}", path, e)))
+    }
+
+    /// Saves the plan to a JSON file so it can be drawn later, possibly on another machine.
+    pub fn save(&self, path: &str) -> Result<(), PrinterError> {
+        let j =
+            serde_json::to_string(self).map_err(|e| PrinterError::InvalidPlan(e.to_string()))?;
+        let mut file = File::create(path)?;
+        file.write_all(j.as_bytes())?;
+        Ok(())
+    }
+
+    /// Appends an operation to the end of the plan.
+    pub fn push(&mut self, op: DrawOp) {
+        self.ops.push(op);
+    }
+
+    /// Appends every operation of another plan to the end of this one.
+    pub fn extend(&mut self, other: DrawPlan) {
+        self.ops.extend(other.ops);
+    }
+
+    /// The number of strokes in the plan.
+    pub fn num_strokes(&self) -> usize {
+        self.ops
+            .iter()
+            .filter(|op| matches!(op, DrawOp::Stroke(_)))
+            .count()
+    }
+}
+
+/// Plans an arbitrary shape drawn as one continuous stroke.
+///
+/// # Arguments
+///
+/// * `points`: The points defining the polygon.
+/// * `color`: The color of the shape.
+/// * `close_shape`: Whether or not to connect the first and last points.
+/// * `fill`: Whether or not to fill the shape. Implies close_shape.
+/// * `brush_size`: The brush size the outline is drawn with and is restored after filling.
+/// * `fill_width`: How many pixels wide the smallest brush, used for filling, paints.
+///
+pub fn plan_shape(
+    points: &[Coord],
+    color: &PaletteColor,
+    close_shape: bool,
+    fill: bool,
+    brush_size: i32,
+    fill_width: u32,
+) -> Result<DrawPlan, PrinterError> {
+    let start_point = match points.first() {
+        Some(p) => *p,
+        None => Err(PrinterError::InvalidPlan(
+            "a shape needs at least one point".to_string(),
+        ))?,
+    };
+
+    let mut stroke = points.to_vec();
+    if close_shape || fill {
+        stroke.push(start_point);
+    }
+
+    let mut plan = DrawPlan::new();
+    plan.push(DrawOp::SetColor(*color));
+    plan.push(DrawOp::Stroke(stroke));
+
+    if fill {
+        plan_fill(&mut plan, points, brush_size, fill_width);
+    }
+
+    Ok(plan)
+}
+
+/// Use the scanline polygon fill algorithm to fill in the polygon.
+///
+/// * `plan` The plan to add the fill to. The fill uses whichever color was last set.
+/// * `points` List of coordinates that define the polygon to fill.
+/// * `brush_size` The brush size to restore once the fill is finished.
+/// * `fill_width` How many pixels wide the smallest brush paints.
+///
+fn plan_fill(plan: &mut DrawPlan, points: &[Coord], brush_size: i32, fill_width: u32) {
+    let mut edges: Vec<[&Coord; 2]> = points.windows(2).map(|pts| [&pts[0], &pts[1]]).collect();
+    edges.push([points.first().unwrap(), points.last().unwrap()]);
+    let slope: Vec<f32> = edges
+        .iter()
+        .map(|pts| {
+            if pts[1].x == pts[0].x {
+                0.0
+            } else {
+                (pts[1].y - pts[0].y) as f32 / (pts[1].x - pts[0].x) as f32
+            }
+        })
+        .collect();
+    let start_y = points.iter().fold(i32::MAX, |acc, pnt| acc.min(pnt.y));
+    let end_y = points.iter().fold(0, |acc, pnt| acc.max(pnt.y));
+    let mut iy = start_y;
+    plan.push(DrawOp::SetBrush(0));
+
+    while iy < end_y {
+        let mut active_edges = vec![];
+        for (edge, slope) in edges.iter().zip(slope.iter()) {
+            let max = edge[0].y.max(edge[1].y);
+            let min = edge[0].y.min(edge[1].y);
+            if max > iy && min < iy && max != min {
+                active_edges.push((edge, slope));
+            }
+        }
+        let x_draw: Vec<i32> = active_edges
+            .iter()
+            .map(|&(pts, m)| {
+                if *m == 0.0 {
+                    pts[0].x
+                } else {
+                    (pts[0].x as f32 + 1.0 / m * (iy - pts[0].y) as f32) as i32
+                }
+            })
+            .collect();
+        let mut in_poly = true;
+        for x in x_draw.windows(2) {
+            if x[0] != x[1] && in_poly {
+                plan.push(DrawOp::Stroke(vec![
+                    Coord::new(x[0], iy),
+                    Coord::new(x[1], iy),
+                ]));
+            }
+            in_poly = !in_poly;
+        }
+
+        // Since the brush size is 0, we increment by half of the width
+        // it paints, but never by less than 6 pixels so that filling
+        // stays quick when the smallest brush is narrow.
+        iy += (fill_width as i32 / 2).max(6);
+    }
+
+    plan.push(DrawOp::SetBrush(brush_size));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(fill_width: u32) -> DrawPlan {
+        let points = [
+            Coord::new(0, 0),
+            Coord::new(100, 0),
+            Coord::new(100, 100),
+            Coord::new(0, 100),
+        ];
+        plan_shape(&points, &PaletteColor::Black, true, true, 2, fill_width).unwrap()
+    }
+
+    #[test]
+    fn filling_steps_at_least_six_pixels_per_row() {
+        // The outline, then a line every 6 pixels from y = 6 to y = 96.
+        assert_eq!(square(1).num_strokes(), 17);
+        assert_eq!(square(12).num_strokes(), 17);
+    }
+
+    #[test]
+    fn filling_steps_by_half_of_a_wide_brush() {
+        // The outline, then a line every 10 pixels from y = 10 to y = 90.
+        assert_eq!(square(20).num_strokes(), 10);
+    }
+
+    #[test]
+    fn filling_restores_the_brush_size() {
+        assert_eq!(square(1).ops.last(), Some(&DrawOp::SetBrush(2)));
+    }
+}
@@ -0,0 +1,89 @@
+use crate::config::ConfigError;
+use crate::error::PrinterError;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// The configuration used when no profile is picked.
+pub const DEFAULT_CONFIG: &str = "coords.json";
+
+/// Finds the directory profiles are stored in: `passpartout_printer` inside the platform's
+/// configuration directory, or the current directory if there isn't one.
+pub fn config_dir() -> PathBuf {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from));
+    match base {
+        Some(base) => base.join("passpartout_printer"),
+        None => PathBuf::from("."),
+    }
+}
+
+/// Works out where the configuration lives. Without a profile this is "coords.json" in the
+/// current directory, otherwise it's the profile's file in `dir`, or in `config_dir()` if no
+/// directory is given. A profile saved as TOML is used if there is one, otherwise JSON.
+///
+/// # Example
+///
+/// ```
+/// use passpartout_printer::profile::config_path;
+/// use std::path::PathBuf;
+///
+/// assert_eq!(config_path(None, None), PathBuf::from("coords.json"));
+/// assert_eq!(
+///     config_path(Some("1440p"), Some("profiles")),
+///     PathBuf::from("profiles/1440p.json"),
+/// );
+/// ```
+pub fn config_path(profile: Option<&str>, dir: Option<&str>) -> PathBuf {
+    match profile {
+        Some(name) => {
+            let dir = dir.map_or_else(config_dir, PathBuf::from);
+            let toml = dir.join(format!("{}.toml", name));
+            if toml.exists() {
+                toml
+            } else {
+                dir.join(format!("{}.json", name))
+            }
+        }
+        None => PathBuf::from(DEFAULT_CONFIG),
+    }
+}
+
+/// Lists the names of the profiles saved in the given directory.
+pub fn list_profiles(dir: Option<&str>) -> Result<Vec<String>, PrinterError> {
+    let dir = dir.map_or_else(config_dir, PathBuf::from);
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == "json" || ext == "toml")
+        })
+        .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .collect();
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Parses a screen resolution written as width by height, such as "2560x1440".
+pub fn parse_resolution(resolution: &str) -> Result<(u32, u32), ConfigError> {
+    let invalid = || {
+        ConfigError::Invalid(format!(
+            "invalid resolution {}, expected something like 2560x1440",
+            resolution
+        ))
+    };
+    let (width, height) = resolution.split_once('x').ok_or_else(invalid)?;
+    let width: u32 = width.trim().parse().map_err(|_| invalid())?;
+    let height: u32 = height.trim().parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        Err(invalid())?
+    }
+    Ok((width, height))
+}
@@ -0,0 +1,512 @@
+use crate::backend::PointerBackend;
+use crate::capture::ScreenCapture;
+use crate::colors::PaletteColor;
+use crate::coords::Coord;
+use crate::easel::{EaselCoords, EaselElement, Orientation, Tool, NUM_BRUSH_STEPS};
+use crate::estimate::{Estimate, Estimator};
+use image::{ImageBuffer, Rgba, RgbaImage};
+use std::error::Error;
+use std::time::Duration;
+
+/// A backend that pretends to be the game. Clicks on the easel's buttons and palette change the
+/// simulated brush, color, tool and orientation, and every stroke dragged across the canvas is
+/// painted into an in-memory image, giving a preview of what would be drawn in-game.
+///
+/// The simulation starts from the same state as a freshly booted game: a blank portrait canvas,
+/// the paintbrush tool, the smallest brush and black paint. Switching orientation starts a fresh
+/// canvas.
+///
+/// # Example
+///
+/// ```
+/// use passpartout_printer::{
+///     colors::PaletteColor,
+///     config::{parse, ConfigFormat},
+///     coords::Coord,
+///     easel::Easel,
+///     simulated::SimulatedEasel,
+/// };
+/// use std::time::Duration;
+///
+/// let coords = parse(r#"{
+///     "portrait_bounds": [{"x": 100, "y": 100}, {"x": 300, "y": 400}],
+///     "landscape_bounds": [{"x": 50, "y": 150}, {"x": 450, "y": 350}],
+///     "paintbrush": {"x": 10, "y": 10},
+///     "spray_can": {"x": 10, "y": 30},
+///     "pen": {"x": 10, "y": 50},
+///     "decrease_brush": {"x": 10, "y": 70},
+///     "increase_brush": {"x": 10, "y": 90},
+///     "change_orientation": {"x": 10, "y": 110},
+///     "color_start": {"x": 500, "y": 100},
+///     "color_row_step": 20,
+///     "color_col_step": 20
+/// }"#, ConfigFormat::Json, "coords.json").unwrap();
+///
+/// let simulated = SimulatedEasel::new(&coords);
+/// let mut easel = Easel::with_coords(coords, simulated, Duration::from_millis(7));
+/// easel.draw_line(Coord::new(0, 10), Coord::new(50, 10), &PaletteColor::Red).unwrap();
+///
+/// let canvas = easel.mouse.canvas();
+/// assert_eq!(*canvas.get_pixel(25, 10), PaletteColor::Red.get_rgba());
+/// assert_eq!(*canvas.get_pixel(25, 20), PaletteColor::White.get_rgba());
+/// ```
+pub struct SimulatedEasel {
+    easel_coords: EaselCoords,
+    canvas: RgbaImage,
+    orientation: Orientation,
+    brush_size: i32,
+    color: PaletteColor,
+    tool: Tool,
+
+    // The last position the cursor was moved to.
+    cursor: Coord,
+
+    // Whether the left mouse button is currently held down.
+    pressed: bool,
+
+    // Whether the current press started a stroke on the canvas rather than a button click.
+    stroking: bool,
+
+    // Counts the operations performed on the simulated easel.
+    estimator: Estimator,
+
+    /// Every position the cursor has been moved to, in screen coordinates.
+    pub path: Vec<Coord>,
+}
+
+impl SimulatedEasel {
+    /// Creates a simulated easel laid out according to the given coordinates.
+    pub fn new(easel_coords: &EaselCoords) -> SimulatedEasel {
+        let orientation = Orientation::Portrait;
+        SimulatedEasel {
+            canvas: blank_canvas(easel_coords, orientation),
+            easel_coords: easel_coords.clone(),
+            orientation,
+            brush_size: 0,
+            color: PaletteColor::Black,
+            tool: Tool::Paintbrush,
+            cursor: Coord::new(0, 0),
+            pressed: false,
+            stroking: false,
+            estimator: Estimator::new(easel_coords),
+            path: vec![],
+        }
+    }
+
+    /// The painted canvas. Pixel (0, 0) is the upper-left corner of the easel.
+    pub fn canvas(&self) -> &RgbaImage {
+        &self.canvas
+    }
+
+    /// Consumes the simulation, returning the painted canvas.
+    pub fn into_canvas(self) -> RgbaImage {
+        self.canvas
+    }
+
+    /// The operations performed on the simulated easel so far and how long they would take
+    /// in-game.
+    pub fn estimate(&self) -> Estimate {
+        self.estimator.estimate
+    }
+
+    /// Whether the left mouse button is currently held down.
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// The orientation the simulated easel is currently in.
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// The brush size the simulated easel is currently painting with.
+    pub fn brush_size(&self) -> i32 {
+        self.brush_size
+    }
+
+    /// The color the simulated easel is currently painting with.
+    pub fn color(&self) -> PaletteColor {
+        self.color
+    }
+
+    /// The tool the simulated easel currently has selected.
+    pub fn tool(&self) -> Tool {
+        self.tool
+    }
+
+    fn click(&mut self, element: EaselElement) {
+        match element {
+            EaselElement::Canvas => (),
+            EaselElement::Tool(tool) => self.tool = tool,
+            EaselElement::DecreaseBrush => self.brush_size = (self.brush_size - 1).max(0),
+            EaselElement::IncreaseBrush => {
+                self.brush_size = (self.brush_size + 1).min(NUM_BRUSH_STEPS)
+            }
+            EaselElement::ChangeOrientation => {
+                self.orientation = match self.orientation {
+                    Orientation::Portrait => Orientation::Landscape,
+                    Orientation::Landscape => Orientation::Portrait,
+                };
+                self.canvas = blank_canvas(&self.easel_coords, self.orientation);
+            }
+            EaselElement::Color(color) => self.color = color,
+        }
+    }
+
+    /// Paints a segment between two screen coordinates with the current brush.
+    fn paint_segment(&mut self, from: Coord, to: Coord) {
+        let (ul, _) = self.easel_coords.bounds(self.orientation);
+        let from = &from - &ul;
+        let to = &to - &ul;
+        let delta = &to - &from;
+        let steps = delta.x.abs().max(delta.y.abs());
+        for step in 0..=steps {
+            let t = if steps == 0 {
+                0.0
+            } else {
+                step as f32 / steps as f32
+            };
+            let x = from.x as f32 + delta.x as f32 * t;
+            let y = from.y as f32 + delta.y as f32 * t;
+            self.stamp(Coord::new(x.round() as i32, y.round() as i32));
+        }
+    }
+
+    /// Paints a single dab of the brush centered on the given canvas coordinate.
+    fn stamp(&mut self, center: Coord) {
+        let radius = self.easel_coords.brush_width(self.brush_size) as f32 / 2.0;
+        let reach = radius as i32;
+        let rgba: Rgba<u8> = self.color.get_rgba();
+        let (width, height) = self.canvas.dimensions();
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                if (dx * dx + dy * dy) as f32 > radius * radius {
+                    continue;
+                }
+                let x = center.x + dx;
+                let y = center.y + dy;
+                if x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height {
+                    self.canvas.put_pixel(x as u32, y as u32, rgba);
+                }
+            }
+        }
+    }
+}
+
+impl PointerBackend for SimulatedEasel {
+    fn move_to(&mut self, x: i32, y: i32) {
+        let pos = Coord::new(x, y);
+        if self.stroking {
+            self.paint_segment(self.cursor, pos);
+        }
+        self.cursor = pos;
+        self.path.push(pos);
+        self.estimator.move_to(x, y);
+    }
+
+    fn press(&mut self) {
+        if self.pressed {
+            return;
+        }
+        self.pressed = true;
+        self.estimator.press();
+        match self.easel_coords.element_at(self.orientation, self.cursor) {
+            Some(EaselElement::Canvas) | None => {
+                self.stroking = true;
+                self.paint_segment(self.cursor, self.cursor);
+            }
+            Some(element) => self.click(element),
+        }
+    }
+
+    fn release(&mut self) {
+        self.pressed = false;
+        self.stroking = false;
+        self.estimator.release();
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.estimator.sleep(duration);
+    }
+}
+
+/// Captures the simulated canvas as it would appear on screen. Only the canvas is simulated, so
+/// everything around it comes out fully transparent.
+impl ScreenCapture for SimulatedEasel {
+    fn capture(
+        &mut self,
+        upper_left: Coord,
+        lower_right: Coord,
+    ) -> Result<RgbaImage, Box<dyn Error>> {
+        let (ul, _) = self.easel_coords.bounds(self.orientation);
+        let width = (lower_right.x - upper_left.x + 1).max(0) as u32;
+        let height = (lower_right.y - upper_left.y + 1).max(0) as u32;
+        let (canvas_width, canvas_height) = self.canvas.dimensions();
+        Ok(RgbaImage::from_fn(width, height, |x, y| {
+            let pos = upper_left + &Coord::new(x as i32, y as i32) - &ul;
+            if pos.x >= 0
+                && pos.y >= 0
+                && (pos.x as u32) < canvas_width
+                && (pos.y as u32) < canvas_height
+            {
+                *self.canvas.get_pixel(pos.x as u32, pos.y as u32)
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        }))
+    }
+}
+
+/// Creates an unpainted canvas the size of the easel in the given orientation.
+fn blank_canvas(easel_coords: &EaselCoords, orientation: Orientation) -> RgbaImage {
+    let (ul, lr) = easel_coords.bounds(orientation);
+    let width = (lr.x - ul.x + 1).max(0) as u32;
+    let height = (lr.y - ul.y + 1).max(0) as u32;
+    ImageBuffer::from_pixel(width, height, PaletteColor::White.get_rgba())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::colors::Palette;
+    use crate::config::{parse, ConfigFormat};
+    use crate::easel::Easel;
+    use crate::error::PrinterError;
+    use crate::plan::{DrawOp, DrawPlan};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// A configuration with a 201x301 portrait easel and the buttons off to its left.
+    pub(crate) fn coords() -> EaselCoords {
+        parse(
+            r#"{
+                "portrait_bounds": [{"x": 100, "y": 100}, {"x": 300, "y": 400}],
+                "landscape_bounds": [{"x": 50, "y": 150}, {"x": 450, "y": 350}],
+                "paintbrush": {"x": 10, "y": 10},
+                "spray_can": {"x": 10, "y": 30},
+                "pen": {"x": 10, "y": 50},
+                "decrease_brush": {"x": 10, "y": 70},
+                "increase_brush": {"x": 10, "y": 90},
+                "change_orientation": {"x": 10, "y": 110},
+                "color_start": {"x": 500, "y": 100},
+                "color_row_step": 20,
+                "color_col_step": 20
+            }"#,
+            ConfigFormat::Json,
+            "coords.json",
+        )
+        .unwrap()
+    }
+
+    pub(crate) fn easel() -> Easel<SimulatedEasel> {
+        let coords = coords();
+        let simulated = SimulatedEasel::new(&coords);
+        Easel::with_coords(coords, simulated, Duration::from_millis(7))
+    }
+
+    fn painted(canvas: &RgbaImage) -> Vec<(u32, u32)> {
+        let white = PaletteColor::White.get_rgba();
+        canvas
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel != white)
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    }
+
+    #[test]
+    fn smallest_brush_paints_a_one_pixel_line() {
+        let mut easel = easel();
+        easel
+            .draw_line(Coord::new(10, 20), Coord::new(40, 20), &PaletteColor::Red)
+            .unwrap();
+
+        let expected: Vec<(u32, u32)> = (10..=40).map(|x| (x, 20)).collect();
+        assert_eq!(painted(easel.mouse.canvas()), expected);
+    }
+
+    #[test]
+    fn diagonal_strokes_have_no_gaps() {
+        let mut easel = easel();
+        easel
+            .draw_line(Coord::new(0, 0), Coord::new(30, 60), &PaletteColor::Red)
+            .unwrap();
+
+        let pixels = painted(easel.mouse.canvas());
+        for y in 0..=60 {
+            assert!(pixels.iter().any(|&(_, py)| py == y), "row {} is empty", y);
+        }
+        let red = PaletteColor::Red.get_rgba();
+        assert!(pixels
+            .iter()
+            .all(|&(x, y)| *easel.mouse.canvas().get_pixel(x, y) == red));
+    }
+
+    #[test]
+    fn larger_brushes_paint_a_round_dab() {
+        let mut easel = easel();
+        easel.change_brush_size(4);
+        easel
+            .execute(&DrawOp::Stroke(vec![Coord::new(50, 50)]))
+            .unwrap();
+
+        // The guessed width at brush 4 is 13 pixels, so the dab reaches 6 pixels out.
+        let pixels = painted(easel.mouse.canvas());
+        assert!(pixels.contains(&(56, 50)) && pixels.contains(&(50, 44)));
+        assert!(!pixels.contains(&(57, 50)) && !pixels.contains(&(55, 55)));
+        assert!(pixels
+            .iter()
+            .all(|&(x, y)| (x as i32 - 50).pow(2) + (y as i32 - 50).pow(2) <= 42));
+    }
+
+    #[test]
+    fn the_button_is_let_go_after_every_stroke() {
+        let mut easel = easel();
+        let plan = DrawPlan {
+            ops: vec![
+                DrawOp::Stroke(vec![Coord::new(0, 0), Coord::new(10, 10)]),
+                DrawOp::Stroke(vec![Coord::new(20, 20)]),
+            ],
+        };
+        easel.execute_plan(&plan).unwrap();
+        assert!(!easel.mouse.is_pressed());
+        assert_eq!(easel.mouse.estimate().strokes, 2);
+    }
+
+    #[test]
+    fn strokes_off_the_easel_never_press_the_button() {
+        let mut easel = easel();
+        let presses = easel.mouse.estimate().presses;
+        let result = easel.execute(&DrawOp::Stroke(vec![Coord::new(0, 0), Coord::new(500, 0)]));
+
+        assert!(matches!(result, Err(PrinterError::OutOfBounds { .. })));
+        assert!(!easel.mouse.is_pressed());
+        assert_eq!(easel.mouse.estimate().presses, presses);
+        assert!(painted(easel.mouse.canvas()).is_empty());
+    }
+
+    /// Wraps the simulated easel to interrupt a stroke partway through: once the button has
+    /// been held for the given number of moves, it either sets the stop flag, as the emergency
+    /// stop key does, or panics.
+    struct Interrupting {
+        easel: SimulatedEasel,
+        moves: usize,
+        stop: Option<Arc<AtomicBool>>,
+        releases: usize,
+    }
+
+    impl PointerBackend for Interrupting {
+        fn move_to(&mut self, x: i32, y: i32) {
+            if self.easel.is_pressed() {
+                if self.moves == 0 {
+                    match &self.stop {
+                        Some(stop) => stop.store(true, Ordering::SeqCst),
+                        None => panic!("the backend went away"),
+                    }
+                }
+                self.moves = self.moves.saturating_sub(1);
+            }
+            self.easel.move_to(x, y);
+        }
+
+        fn press(&mut self) {
+            self.easel.press();
+        }
+
+        fn release(&mut self) {
+            self.releases += 1;
+            self.easel.release();
+        }
+
+        fn sleep(&mut self, duration: Duration) {
+            self.easel.sleep(duration);
+        }
+    }
+
+    fn interrupting_easel(stop: bool) -> Easel<Interrupting> {
+        let coords = coords();
+        let flag = Arc::new(AtomicBool::new(false));
+        let mouse = Interrupting {
+            easel: SimulatedEasel::new(&coords),
+            moves: 2,
+            stop: if stop { Some(flag.clone()) } else { None },
+            releases: 0,
+        };
+        let mut easel = Easel::with_coords(coords, mouse, Duration::from_millis(7));
+        easel.stop = flag;
+        easel
+    }
+
+    /// A stroke long enough to still be going when it's interrupted.
+    fn long_stroke() -> DrawOp {
+        DrawOp::Stroke((0..10).map(|x| Coord::new(x * 10, 5)).collect())
+    }
+
+    #[test]
+    fn stopping_partway_through_a_stroke_lets_go_of_the_button() {
+        let mut easel = interrupting_easel(true);
+        let (presses, releases) = (easel.mouse.easel.estimate().presses, easel.mouse.releases);
+        let result = easel.execute(&long_stroke());
+
+        assert!(matches!(result, Err(PrinterError::Aborted)));
+        assert_eq!(easel.mouse.easel.estimate().presses, presses + 1);
+        assert_eq!(easel.mouse.releases, releases + 1);
+        assert!(!easel.mouse.easel.is_pressed());
+
+        // The stroke was cut short rather than finished.
+        let canvas = easel.mouse.easel.canvas();
+        assert_eq!(*canvas.get_pixel(5, 5), PaletteColor::Black.get_rgba());
+        assert_eq!(*canvas.get_pixel(90, 5), PaletteColor::White.get_rgba());
+
+        // Nothing more is drawn once the drawing has been stopped.
+        let result = easel.execute(&long_stroke());
+        assert!(matches!(result, Err(PrinterError::Aborted)));
+        assert_eq!(easel.mouse.easel.estimate().presses, presses + 1);
+    }
+
+    #[test]
+    fn a_panic_partway_through_a_stroke_lets_go_of_the_button() {
+        let mut easel = interrupting_easel(false);
+        let (presses, releases) = (easel.mouse.easel.estimate().presses, easel.mouse.releases);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| easel.execute(&long_stroke())));
+
+        assert!(result.is_err());
+        assert_eq!(easel.mouse.easel.estimate().presses, presses + 1);
+        assert_eq!(easel.mouse.releases, releases + 1);
+        assert!(!easel.mouse.easel.is_pressed());
+    }
+
+    #[test]
+    fn clicks_on_the_palette_change_the_color() {
+        let mut easel = easel();
+        for color in Palette::new().colormap {
+            easel.change_color(&color);
+            assert_eq!(easel.mouse.color(), color);
+        }
+        easel
+            .draw_line(
+                Coord::new(0, 5),
+                Coord::new(5, 5),
+                &PaletteColor::LightViolet,
+            )
+            .unwrap();
+        assert_eq!(
+            *easel.mouse.canvas().get_pixel(3, 5),
+            PaletteColor::LightViolet.get_rgba()
+        );
+    }
+
+    #[test]
+    fn clicks_on_the_buttons_change_the_brush_tool_and_orientation() {
+        let mut easel = easel();
+        easel.change_brush_size(NUM_BRUSH_STEPS);
+        assert_eq!(easel.mouse.brush_size(), NUM_BRUSH_STEPS);
+        easel.change_brush_size(3);
+        assert_eq!(easel.mouse.brush_size(), 3);
+        easel.change_tool(Tool::Pen);
+        assert_eq!(easel.mouse.tool(), Tool::Pen);
+        easel.change_orientation();
+        assert_eq!(easel.mouse.orientation(), Orientation::Landscape);
+        assert_eq!(easel.mouse.canvas().dimensions(), (401, 201));
+    }
+}
@@ -0,0 +1,225 @@
+use crate::capture::ScreenCapture;
+use crate::colors::{Palette, PaletteColor};
+use crate::coords::Coord;
+use crate::easel::{Easel, EaselCoords, Orientation, Tool};
+use crate::error::PrinterError;
+use crate::plan::{DrawOp, DrawPlan};
+use crate::simulated::SimulatedEasel;
+use image::imageops::ColorMap;
+use image::RgbaImage;
+use std::time::Duration;
+
+/// The outcome of comparing what was drawn on the easel against what should have been drawn.
+pub struct Verification {
+    /// The number of pixels of the easel that came out in the wrong color.
+    pub wrong_pixels: usize,
+
+    /// The number of pixels of the easel that were compared.
+    pub total_pixels: usize,
+
+    /// A plan that redraws only the runs of pixels that came out wrong.
+    pub repair: DrawPlan,
+}
+
+/// Works out what the easel should look like once a plan has been drawn, by drawing it on a
+/// simulated easel. The result can be handed to `verify` as the target.
+pub fn expected_canvas(
+    plan: &DrawPlan,
+    easel_coords: &EaselCoords,
+) -> Result<RgbaImage, PrinterError> {
+    let simulated = SimulatedEasel::new(easel_coords);
+    let mut easel = Easel::with_coords(easel_coords.clone(), simulated, Duration::from_millis(0));
+    easel.execute_plan(plan)?;
+    Ok(easel.mouse.into_canvas())
+}
+
+/// Captures the easel in the given orientation and compares it against `target`, the canvas
+/// that should have been drawn. Both are matched to the closest colors of `palette`, using its
+/// metric, before being compared, so slight differences in how the game renders colors don't
+/// count as mistakes.
+pub fn verify<C: ScreenCapture>(
+    capture: &mut C,
+    easel_coords: &EaselCoords,
+    orientation: Orientation,
+    target: &RgbaImage,
+    palette: &Palette,
+) -> Result<Verification, PrinterError> {
+    let (ul, lr) = easel_coords.bounds(orientation);
+    let captured = capture.capture(ul, lr).map_err(PrinterError::Backend)?;
+    if captured.dimensions() != target.dimensions() {
+        Err(PrinterError::Backend(
+            format!(
+                "the captured easel is {:?} but {:?} was expected",
+                captured.dimensions(),
+                target.dimensions()
+            )
+            .into(),
+        ))?
+    }
+    Ok(compare(&captured, target, orientation, palette))
+}
+
+/// Compares a captured easel against the target pixel by pixel and plans a repair for every run
+/// of wrong pixels. Runs are grouped by the color they should be, lightest first, so each color
+/// is only picked up once.
+pub fn compare(
+    captured: &RgbaImage,
+    target: &RgbaImage,
+    orientation: Orientation,
+    palette: &Palette,
+) -> Verification {
+    let (width, height) = target.dimensions();
+    let mut runs: Vec<(PaletteColor, Coord, Coord)> = vec![];
+    let mut wrong_pixels = 0;
+
+    for y in 0..height {
+        // The color the current run of wrong pixels should be and where it started.
+        let mut run: Option<(PaletteColor, u32)> = None;
+        for x in 0..=width {
+            let wrong = if x < width {
+                let want = palette.colormap[palette.index_of(target.get_pixel(x, y))];
+                let got = palette.colormap[palette.index_of(captured.get_pixel(x, y))];
+                if want != got {
+                    wrong_pixels += 1;
+                    Some(want)
+                } else {
+                    None
+                }
+            } else {
+                None
+            };
+
+            if let Some((color, start)) = run {
+                if wrong != Some(color) {
+                    runs.push((
+                        color,
+                        Coord::new(start as i32, y as i32),
+                        Coord::new(x as i32 - 1, y as i32),
+                    ));
+                    run = None;
+                }
+            }
+            if let (Some(color), None) = (wrong, run) {
+                run = Some((color, x));
+            }
+        }
+    }
+
+    // The sort is stable, so runs of the same color stay in scanline order.
+    runs.sort_by(|(a, _, _), (b, _, _)| b.lightness().total_cmp(&a.lightness()));
+
+    let mut repair = DrawPlan::new();
+    if !runs.is_empty() {
+        repair.push(DrawOp::SetOrientation(orientation));
+        repair.push(DrawOp::SelectTool(Tool::Paintbrush));
+        repair.push(DrawOp::SetBrush(0));
+        let mut plan_color = None;
+        for (color, start, end) in runs {
+            if plan_color != Some(color) {
+                repair.push(DrawOp::SetColor(color));
+                plan_color = Some(color);
+            }
+            repair.push(DrawOp::Stroke(vec![start, end]));
+        }
+    }
+
+    Verification {
+        wrong_pixels,
+        total_pixels: (width * height) as usize,
+        repair,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::FileCapture;
+    use crate::colors::ColorMetric;
+    use crate::simulated::tests::coords;
+    use image::Rgba;
+
+    /// Red rows at the top of the easel and dark blue rows further down.
+    fn plan() -> DrawPlan {
+        let mut plan = DrawPlan::new();
+        plan.push(DrawOp::SetOrientation(Orientation::Portrait));
+        plan.push(DrawOp::SelectTool(Tool::Paintbrush));
+        plan.push(DrawOp::SetBrush(0));
+        for (color, rows) in [
+            (PaletteColor::Red, 10..=30),
+            (PaletteColor::DarkBlue, 100..=120),
+        ] {
+            plan.push(DrawOp::SetColor(color));
+            for y in rows {
+                plan.push(DrawOp::Stroke(vec![Coord::new(20, y), Coord::new(150, y)]));
+            }
+        }
+        plan
+    }
+
+    #[test]
+    fn only_the_defects_in_a_screenshot_are_repaired() {
+        // A screenshot of the plan drawn on the easel, which is at (100, 100) on screen, with
+        // a gap in one of the red rows, a black smudge on a blue row and a stray black line in
+        // the white space below.
+        let mut capture = FileCapture::open("tests/fixtures/easel_with_defects.png").unwrap();
+        let coords = coords();
+        let target = expected_canvas(&plan(), &coords).unwrap();
+        let palette = Palette::new();
+        let verification = verify(
+            &mut capture,
+            &coords,
+            Orientation::Portrait,
+            &target,
+            &palette,
+        )
+        .unwrap();
+
+        assert_eq!(verification.wrong_pixels, 20 + 5 + 10);
+        assert_eq!(verification.total_pixels, 201 * 301);
+        assert_eq!(
+            verification.repair.ops,
+            vec![
+                DrawOp::SetOrientation(Orientation::Portrait),
+                DrawOp::SelectTool(Tool::Paintbrush),
+                DrawOp::SetBrush(0),
+                DrawOp::SetColor(PaletteColor::White),
+                DrawOp::Stroke(vec![Coord::new(30, 200), Coord::new(39, 200)]),
+                DrawOp::SetColor(PaletteColor::Red),
+                DrawOp::Stroke(vec![Coord::new(50, 15), Coord::new(69, 15)]),
+                DrawOp::SetColor(PaletteColor::DarkBlue),
+                DrawOp::Stroke(vec![Coord::new(100, 105), Coord::new(104, 105)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn colors_are_matched_with_the_metric_of_the_palette() {
+        let rgb = Palette::with_metric(ColorMetric::Rgb);
+        let ciede2000 = Palette::with_metric(ColorMetric::Ciede2000);
+
+        // Find a color the two metrics match to different palette colors.
+        let color = (0..=255u8)
+            .step_by(5)
+            .flat_map(|r| {
+                (0..=255u8)
+                    .step_by(5)
+                    .map(move |g| Rgba([r, g, 0x80, 0xff]))
+            })
+            .find(|color| {
+                rgb.colormap[rgb.index_of(color)] != ciede2000.colormap[ciede2000.index_of(color)]
+            })
+            .unwrap();
+
+        // Captured in that color where the closest color by RGB distance was wanted.
+        let mut wanted = color;
+        rgb.map_color(&mut wanted);
+        let target = RgbaImage::from_pixel(4, 2, wanted);
+        let captured = RgbaImage::from_pixel(4, 2, color);
+        let by_rgb = compare(&captured, &target, Orientation::Landscape, &rgb);
+        assert_eq!(by_rgb.wrong_pixels, 0);
+        assert!(by_rgb.repair.ops.is_empty());
+        let by_ciede2000 = compare(&captured, &target, Orientation::Landscape, &ciede2000);
+        assert_eq!(by_ciede2000.wrong_pixels, 8);
+        assert_eq!(by_ciede2000.repair.num_strokes(), 2);
+    }
+}
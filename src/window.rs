@@ -0,0 +1,153 @@
+use crate::coords::Coord;
+use std::error::Error;
+
+/// Reports where the game window currently is, so coordinates can be stored relative to the
+/// window rather than the screen and keep working after the window is moved.
+pub trait WindowProvider {
+    /// The screen coordinates of the upper-left corner of the game window.
+    fn origin(&mut self) -> Result<Coord, Box<dyn Error>>;
+}
+
+/// A window that never moves from the given position.
+///
+/// # Example
+///
+/// ```
+/// use passpartout_printer::{
+///     coords::Coord,
+///     window::{FixedOffset, WindowProvider},
+/// };
+///
+/// let mut window = FixedOffset(Coord::new(100, 50));
+/// assert_eq!(window.origin().unwrap(), Coord::new(100, 50));
+/// ```
+pub struct FixedOffset(pub Coord);
+
+impl WindowProvider for FixedOffset {
+    fn origin(&mut self) -> Result<Coord, Box<dyn Error>> {
+        Ok(self.0)
+    }
+}
+
+/// Finds the game window on an X server by its title, looking it up again each time it's asked
+/// for its origin in case the window has been moved.
+#[cfg(target_os = "linux")]
+pub struct X11Window {
+    display: *mut x11::xlib::Display,
+    window: x11::xlib::Window,
+}
+
+#[cfg(target_os = "linux")]
+impl X11Window {
+    /// Connects to the X server and finds the first window whose title contains `title`.
+    pub fn find(title: &str) -> Result<X11Window, Box<dyn Error>> {
+        let display = unsafe { x11::xlib::XOpenDisplay(std::ptr::null()) };
+        if display.is_null() {
+            Err("couldn't connect to the X server")?
+        }
+        let root = unsafe { x11::xlib::XDefaultRootWindow(display) };
+        match unsafe { find_window(display, root, title) } {
+            Some(window) => Ok(X11Window { display, window }),
+            None => {
+                unsafe { x11::xlib::XCloseDisplay(display) };
+                Err(format!("couldn't find a window titled {}", title))?
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl WindowProvider for X11Window {
+    fn origin(&mut self) -> Result<Coord, Box<dyn Error>> {
+        use x11::xlib;
+
+        let (mut x, mut y, mut child) = (0, 0, 0);
+        let found = unsafe {
+            let root = xlib::XDefaultRootWindow(self.display);
+            xlib::XTranslateCoordinates(
+                self.display,
+                self.window,
+                root,
+                0,
+                0,
+                &mut x,
+                &mut y,
+                &mut child,
+            )
+        };
+        if found == 0 {
+            Err("the game window is no longer on the screen")?
+        }
+        Ok(Coord::new(x, y))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for X11Window {
+    fn drop(&mut self) {
+        unsafe {
+            x11::xlib::XCloseDisplay(self.display);
+        }
+    }
+}
+
+/// Searches the window tree below `parent` for a window whose title contains `title`.
+#[cfg(target_os = "linux")]
+unsafe fn find_window(
+    display: *mut x11::xlib::Display,
+    parent: x11::xlib::Window,
+    title: &str,
+) -> Option<x11::xlib::Window> {
+    use std::ffi::CStr;
+    use x11::xlib;
+
+    let mut name = std::ptr::null_mut();
+    if xlib::XFetchName(display, parent, &mut name) != 0 && !name.is_null() {
+        let matches = CStr::from_ptr(name).to_string_lossy().contains(title);
+        xlib::XFree(name as *mut _);
+        if matches {
+            return Some(parent);
+        }
+    }
+
+    let (mut root, mut found_parent) = (0, 0);
+    let mut children = std::ptr::null_mut();
+    let mut num_children = 0;
+    if xlib::XQueryTree(
+        display,
+        parent,
+        &mut root,
+        &mut found_parent,
+        &mut children,
+        &mut num_children,
+    ) == 0
+    {
+        return None;
+    }
+    let mut found = None;
+    if !children.is_null() {
+        for child in std::slice::from_raw_parts(children, num_children as usize) {
+            found = find_window(display, *child, title);
+            if found.is_some() {
+                break;
+            }
+        }
+        xlib::XFree(children as *mut _);
+    }
+    found
+}
+
+/// Finds the game window by its title with whatever the platform supports.
+pub fn find_window_by_title(title: &str) -> Result<Box<dyn WindowProvider>, Box<dyn Error>> {
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Box::new(X11Window::find(title)?))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        Err(format!(
+            "finding the window {} isn't supported on this platform yet",
+            title
+        ))?
+    }
+}
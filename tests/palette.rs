@@ -0,0 +1,42 @@
+//! Picking a palette changes the colors for the rest of the process, so this runs in a test
+//! binary of its own.
+
+use passpartout_printer::colors::{use_palette, Palette, PaletteColor, PaletteEntry};
+use passpartout_printer::coords::Coord;
+
+#[test]
+fn a_picked_palette_is_used_for_the_rest_of_the_run() {
+    let red = PaletteEntry {
+        name: PaletteColor::Red,
+        rgba: [0xf0, 0x10, 0x10, 0xff],
+        row: 0,
+        col: 0,
+    };
+    let white = PaletteColor::White.default_entry();
+    use_palette(vec![red, white]).unwrap();
+
+    assert_eq!(PaletteColor::Red.get_rgba().0, red.rgba);
+    assert_eq!(PaletteColor::Red.get_row_col(), Coord::new(0, 0));
+    assert_eq!(
+        PaletteColor::from_row_col(Coord::new(0, 0)),
+        Some(PaletteColor::Red)
+    );
+    assert_eq!(
+        PaletteColor::from_row_col(Coord::new(0, 2)),
+        Some(PaletteColor::White)
+    );
+
+    // Colors left out keep the game's values, but images aren't matched to them.
+    assert_eq!(
+        PaletteColor::Grey.get_rgba(),
+        image::Rgba(PaletteColor::Grey.default_entry().rgba)
+    );
+    assert_eq!(PaletteColor::from_row_col(Coord::new(0, 1)), None);
+    assert_eq!(
+        Palette::new().colormap,
+        vec![PaletteColor::Red, PaletteColor::White]
+    );
+
+    assert!(use_palette(vec![white]).is_err());
+    assert_eq!(PaletteColor::Red.get_rgba().0, red.rgba);
+}